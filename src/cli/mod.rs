@@ -1,9 +1,15 @@
-use std::fmt::Error;
+use std::sync::RwLock;
+
 use clap::{Args, Parser, Subcommand};
+use rug::Rational;
 
 use crate::ext::chemdraw;
-use crate::model::ReactionList;
-use crate::model::{Compound, Units, YieldUnits};
+use crate::parse;
+use crate::model::{Balancings, Medium, Reactant, ReactionList, RedoxBalance};
+use crate::model::{balance_many, UnorderedBalance};
+use crate::model::{decimal_string, Compound, Units, YieldUnits};
+use crate::model::{self, AdductIon, AtomicWeights};
+use crate::model::{Element, ElementalAnalysis, ANALYSIS_TOLERANCE};
 
 #[derive(Parser)]
 #[clap(name = "stoichkit")]
@@ -11,21 +17,49 @@ use crate::model::{Compound, Units, YieldUnits};
 pub struct Cli {
     #[clap(subcommand)]
     command: Commands,
+    #[clap(long, global = true, help = "JSON file of extra formula abbreviations, e.g. {\"Tol\": \"C7H7\"}")]
+    abbreviations: Option<String>,
+    #[clap(long, global = true, help = "Read formulas without expanding abbreviations such as Ph or OAc")]
+    no_abbreviations: bool,
+    #[clap(long, global = true, arg_enum, help = "Atomic weights for molar masses, defaults to pubchem")]
+    atomic_weights: Option<AtomicWeights>,
+    #[clap(long, global = true, help = "JSON file of atomic weight overrides, e.g. {\"Li\": 6.94}")]
+    atomic_weights_file: Option<String>,
+    #[clap(long, global = true, help = "Round masses, moles and yields to this many decimal places, all digits by default")]
+    precision: Option<usize>,
 }
 
 impl Cli {
     pub fn run(self) {
+        parse::set_abbreviations_enabled(!self.no_abbreviations);
+        if let Some(file) = self.abbreviations {
+            if let Err(err) = parse::load_abbreviations(&file) {
+                println!("ERROR: {}", err);
+                return;
+            }
+        }
+        model::set_atomic_weights(self.atomic_weights.unwrap_or(AtomicWeights::Pubchem));
+        if let Some(file) = self.atomic_weights_file {
+            if let Err(err) = model::load_atomic_weights(&file) {
+                println!("ERROR: {}", err);
+                return;
+            }
+        }
+        *PRECISION.write().expect("Precision poisoned") = self.precision;
         let result = match self.command {
             Commands::TheoreticalYield(
                 TheoreticalYieldArgs { reaction_list, units}
-            ) => Cli::run_theoretical_yield_command(reaction_list, units),
+            ) => Cli::run_theoretical_yield_command(ReactionList::new(reaction_list), units),
             Commands::Yield(
                 YieldArgs { reaction_list }
-            ) => Cli::run_yield_command(reaction_list),
-            Commands::Balance(BalanceEquationArgs{ reaction_list, chemdraw_file, explicit }) =>
-                Cli::run_balance_command(reaction_list, chemdraw_file, explicit),
+            ) => Cli::run_yield_command(ReactionList::new(reaction_list)),
+            Commands::Balance(args) => Cli::run_balance_command(args),
             Commands::Moles(MolesArgs { reaction_list }) =>
-                Cli::run_moles_command(reaction_list),
+                Cli::run_moles_command(ReactionList::new(reaction_list)),
+            Commands::Hydrate(args) => Cli::run_hydrate_command(args),
+            Commands::MassSpec(args) => Cli::run_mass_spec_command(args),
+            Commands::Composition(args) => Cli::run_composition_command(args),
+            Commands::Empirical(args) => Cli::run_empirical_command(args),
         };
         match result {
             Ok(_) => (),
@@ -47,7 +81,8 @@ impl Cli {
                         .map(|(r, amt)| (&r.compound, *amt))
                         .collect(),
                     units.into(),
-                )
+                );
+                print_atomic_weights();
             })
     }
 
@@ -56,78 +91,419 @@ impl Cli {
             print_result_list(
                 vec![(&yld.product.reactant.compound, yld.percent_yield())],
                 Units::Percent,
-            )
+            );
+            print_atomic_weights();
         })
     }
 
-    fn run_balance_command(reaction_list: ReactionList,
-                           chemdraw_file: Option<String>,
-                           explicit: bool) -> Result<(), String> {
+    fn run_balance_command(args: BalanceEquationArgs) -> Result<(), String> {
+        let BalanceEquationArgs { reaction_list, chemdraw_file, explicit, all, redox, medium, per, decimal, auto_sides, matrix, file } = args;
+        let reaction_list = ReactionList::new(reaction_list);
+        // parsed here rather than by clap, once abbreviations and atomic
+        // weights are set
+        let per = per.as_deref().map(parse_pinned_reactant).transpose()?;
+        if let Some(file) = file {
+            return Cli::run_balance_file(&file, explicit, decimal);
+        }
+        // skeleton reactions let a failed balance explain which elements
+        // are missing from a side
         let rxn = match chemdraw_file {
             Some(file) => chemdraw::parse_chemdraw_file(file.as_ref()),
-            None => reaction_list.parse_reaction(),
+            None => reaction_list.parse_skeleton_reaction(),
         }?;
-        let balanced_rxn = rxn.balance();
-        balanced_rxn.map(|balanced| {
-            println!("{}", balanced.display_string(explicit))
+        if redox {
+            return rxn.balance_redox(medium.unwrap_or(Medium::Acidic)).map(|balanced| {
+                print_redox_balance(&balanced, explicit)
+            });
+        }
+        if matrix {
+            println!("{}", rxn.matrix());
+        }
+        if all {
+            return rxn.balance_all().map(|balancings| {
+                print_balancings(&balancings, explicit)
+            });
+        }
+        let balanced_rxn = match auto_sides {
+            true => rxn.balance_unordered().map(|unordered| {
+                // without an "=" no sides were given, so nothing was moved
+                if !rxn.products.is_empty() {
+                    print_moved_species(&unordered);
+                }
+                unordered.balanced
+            }),
+            false => rxn.balance(),
+        }.map_err(String::from);
+        let balanced_rxn = match per {
+            Some(pinned) => balanced_rxn.and_then(|balanced| {
+                balanced.per(&pinned.compound, pinned.molar_coefficient)
+            }),
+            None => balanced_rxn,
+        };
+        balanced_rxn.map(|balanced| match decimal {
+            true => println!("{}", balanced.decimal_display_string(explicit)),
+            false => println!("{}", balanced.display_string(explicit)),
         })
     }
 
+    fn run_balance_file(
+        file: &str,
+        explicit: bool,
+        decimal: bool,
+    ) -> Result<(), String> {
+        let contents = std::fs::read_to_string(file)
+            .map_err(|e| format!("Could not read {}: {}", file, e))?;
+        // skip blank lines and # comments, but report original line numbers
+        let (line_numbers, equations): (Vec<usize>, Vec<&str>) = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .unzip();
+        let results = balance_many(&equations);
+        line_numbers.iter().zip(results).for_each(|(n, result)| match result {
+            Ok(balanced) if decimal => println!("{}", balanced.decimal_display_string(explicit)),
+            Ok(balanced) => println!("{}", balanced.display_string(explicit)),
+            Err(err) => println!("ERROR (line {}): {}", n, err),
+        });
+        Ok(())
+    }
+
     fn run_moles_command(reaction_list: ReactionList) -> Result<(), String> {
         reaction_list.to_samples().map(|subs| {
             subs.iter().for_each(|s| {
-                println!("{} mol", s.mass / s.reactant.compound.molar_mass)
-            })
+                println!("{} mol", number(s.mass / s.reactant.compound.molar_mass))
+            });
+            print_atomic_weights();
         })
     }
+
+    fn run_hydrate_command(args: HydrateArgs) -> Result<(), String> {
+        let HydrateArgs { formula, water, mass } = args;
+        let compound = Compound::from_formula(&formula)?;
+        let anhydrous = compound.anhydrous();
+        println!("{} {} g/mol", compound.formula, number(compound.molar_mass));
+        println!("Anhydrous {} {} g/mol", anhydrous.formula, number(anhydrous.molar_mass));
+        println!(
+            "Water of crystallization {} H2O {} g/mol ({}%)",
+            decimal_string(&compound.water_count()),
+            number(compound.water_mass()),
+            number(compound.water_mass() / compound.molar_mass * 100.0)
+        );
+        let target = match water {
+            Some(count) => {
+                let target = compound.with_water(count);
+                println!("{} {} g/mol", target.formula, number(target.molar_mass));
+                target
+            }
+            None => anhydrous,
+        };
+        if let Some(mass) = mass {
+            let converted = compound.hydrate_mass(mass, &target)?;
+            println!("{} g {} = {} g {}", mass, compound.formula, number(converted), target.formula);
+        }
+        print_atomic_weights();
+        Ok(())
+    }
+
+    fn run_mass_spec_command(args: MassSpecArgs) -> Result<(), String> {
+        let MassSpecArgs { formula, min_intensity, adducts, adduct } = args;
+        let compound = Compound::from_formula(&formula)?;
+        println!("{} monoisotopic mass {:.6} Da", compound.formula, compound.monoisotopic_mass()?);
+        println!("m/z intensity");
+        compound
+            .isotope_pattern()?
+            .iter()
+            .filter(|peak| peak.intensity >= min_intensity)
+            .for_each(|peak| println!("{}", peak));
+        let ions = match adduct.is_empty() {
+            true if adducts => AdductIon::defaults(),
+            true => vec![],
+            false => adduct,
+        };
+        if !ions.is_empty() {
+            println!("Ion m/z");
+        }
+        ions.iter().for_each(|ion| match ion.mz(&compound) {
+            Ok(mz) => println!("{} {:.4}", ion, mz),
+            Err(err) => println!("{} ERROR: {}", ion, err),
+        });
+        Ok(())
+    }
+
+    fn run_composition_command(args: CompositionArgs) -> Result<(), String> {
+        let CompositionArgs { formula, found, tolerance, solvates, solvent, step, max_solvent } = args;
+        let compound = Compound::from_formula(&formula)?;
+        println!("{} {} g/mol", compound.formula, number(compound.molar_mass));
+        compound
+            .mass_percent()
+            .iter()
+            .for_each(|(element, percent)| println!("{} {:.2}%", element, percent));
+        if !found.is_empty() {
+            let analysis = ElementalAnalysis::new(&compound, &found);
+            println!("Element calculated found deviation");
+            analysis.rows.iter().for_each(|row| {
+                println!("{} {:.2} {:.2} {:+.2}", row.element, row.calculated, row.found, row.deviation())
+            });
+            println!("{} (±{})", pass_or_fail(&analysis, tolerance), tolerance);
+        }
+        if solvates || !solvent.is_empty() {
+            let solvents = match solvent.is_empty() {
+                true => ElementalAnalysis::common_solvents(),
+                false => solvent
+                    .iter()
+                    .map(|formula| Compound::from_formula(formula))
+                    .collect::<Result<Vec<Compound>, String>>()?,
+            };
+            let fits = ElementalAnalysis::fit_solvates(&compound, &found, &solvents, &step, &max_solvent)?;
+            println!("Best solvate fits:");
+            fits.iter().take(SOLVATE_FITS).for_each(|fit| {
+                println!(
+                    "  {} max deviation {:.2} {}",
+                    fit.compound.formula,
+                    fit.max_deviation(),
+                    pass_or_fail(fit, tolerance)
+                )
+            });
+        }
+        print_atomic_weights();
+        Ok(())
+    }
+
+    fn run_empirical_command(args: EmpiricalArgs) -> Result<(), String> {
+        let EmpiricalArgs { percents, tolerance, molar_mass } = args;
+        let total: f64 = percents.iter().map(|(_, percent)| percent).sum();
+        if (total - 100.0).abs() > tolerance * percents.len() as f64 {
+            println!("WARNING: percentages add up to {:.2}, not 100", total);
+        }
+        let analyses = ElementalAnalysis::empirical_formulas(&percents, tolerance)?;
+        let lines = analyses
+            .iter()
+            .map(|analysis| {
+                let compound = &analysis.compound;
+                let molecular = match molar_mass {
+                    Some(molar_mass) => {
+                        let molecular = compound.molecular_formula(molar_mass)?;
+                        format!(", molecular formula {} {} g/mol", molecular.formula, number(molecular.molar_mass))
+                    }
+                    None => String::new(),
+                };
+                Ok(format!(
+                    "  {} {} g/mol max deviation {:.2}{}",
+                    compound.formula,
+                    number(compound.molar_mass),
+                    analysis.max_deviation(),
+                    molecular
+                ))
+            })
+            .collect::<Result<Vec<String>, String>>()?;
+        println!("Empirical formulas, best fit first:");
+        lines.iter().for_each(|line| println!("{}", line));
+        print_atomic_weights();
+        Ok(())
+    }
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     TheoreticalYield(TheoreticalYieldArgs),
     Yield(YieldArgs),
     Balance(BalanceEquationArgs),
     Moles(MolesArgs), // Pvnrt(GasArgs) TODO,
+    Hydrate(HydrateArgs),
+    MassSpec(MassSpecArgs),
+    Composition(CompositionArgs),
+    Empirical(EmpiricalArgs),
 }
 
 #[derive(Args)]
 struct TheoreticalYieldArgs {
-    #[clap(parse(try_from_str = parse_reaction_list))]
-    reaction_list: ReactionList,
+    #[clap(multiple_values = true, required = true)]
+    reaction_list: Vec<String>,
     #[clap(short, long, arg_enum)]
     units: Option<YieldUnits>,
 }
 
-fn parse_reaction_list(arg: &str) -> Result<ReactionList, Error> {
-    let args = arg.split(" ").map(String::from).collect();
-    Ok(ReactionList::new(args))
-}
 
 #[derive(Args)]
 struct YieldArgs {
-    #[clap(parse(try_from_str = parse_reaction_list))]
-    reaction_list: ReactionList,
+    #[clap(multiple_values = true, required = true)]
+    reaction_list: Vec<String>,
 }
 
 #[derive(Args)]
 struct BalanceEquationArgs {
-    #[clap(help = "Chemical equation [...reactants] = [...products]")]
-    #[clap(parse(try_from_str = parse_reaction_list))]
-    reaction_list: ReactionList,
-    #[clap(short, conflicts_with = "substances")]
+    #[clap(help = "Chemical equation, e.g. \"2H2 + O2 -> 2H2O\" or H2 O2 = H2O")]
+    #[clap(multiple_values = true)]
+    #[clap(required_unless_present_any = &["chemdraw-file", "file"])]
+    reaction_list: Vec<String>,
+    #[clap(short)]
     chemdraw_file: Option<String>,
+    #[clap(long, conflicts_with_all = &["all", "redox", "per", "auto-sides"])]
+    #[clap(help = "Balance every line of a file of equations, in parallel")]
+    file: Option<String>,
     #[clap(short = 'x', long)]
     explicit: bool,
+    #[clap(short, long, help = "List every independent balancing")]
+    all: bool,
+    #[clap(long, help = "Balance a skeleton redox equation by half-reactions")]
+    redox: bool,
+    #[clap(long, arg_enum, requires = "redox", help = "Defaults to acidic")]
+    medium: Option<Medium>,
+    #[clap(long, conflicts_with_all = &["all", "redox"])]
+    #[clap(help = "Scale to one mole of a species, or e.g. 2*H2 for two moles")]
+    per: Option<String>,
+    #[clap(short, long, conflicts_with_all = &["all", "redox"])]
+    #[clap(help = "Print coefficients as decimals")]
+    decimal: bool,
+    #[clap(long, conflicts_with_all = &["all", "redox"])]
+    #[clap(help = "Let the balancer decide which side each species is on")]
+    auto_sides: bool,
+    #[clap(long, conflicts_with_all = &["file", "redox"])]
+    #[clap(help = "Print the stoichiometric matrix before balancing")]
+    matrix: bool,
+}
+
+fn parse_pinned_reactant(arg: &str) -> Result<Reactant, String> {
+    match arg.split_once('*') {
+        Some((coefficient, formula)) => {
+            Reactant::from_formula(formula, parse::parse_coefficient(coefficient)?)
+        }
+        None => Reactant::from_formula(arg, 1),
+    }
 }
 
 #[derive(Args)]
 struct MolesArgs {
-    #[clap(parse(try_from_str = parse_reaction_list))]
-    reaction_list: ReactionList
+    #[clap(multiple_values = true, required = true)]
+    reaction_list: Vec<String>,
+}
+
+#[derive(Args)]
+struct HydrateArgs {
+    #[clap(help = "Formula with adducts, e.g. CuSO4·5H2O or CuSO4*5H2O")]
+    formula: String,
+    #[clap(short, long, help = "Convert to the form with this many waters, e.g. 2 or 0.5")]
+    #[clap(parse(try_from_str = parse::parse_coefficient))]
+    water: Option<Rational>,
+    #[clap(short, long, help = "Convert a mass (grams) to the anhydrous or --water form")]
+    mass: Option<f64>,
+}
+
+#[derive(Args)]
+struct MassSpecArgs {
+    #[clap(help = "Formula of the molecule or ion, e.g. C6H12O6 or SO4^2-")]
+    formula: String,
+    #[clap(long, default_value = "0.1", help = "Leave out peaks below this percent of the largest")]
+    min_intensity: f64,
+    #[clap(long, help = "Print the m/z of common ESI adduct ions, from [M+H]+ to [M+Cl]-")]
+    adducts: bool,
+    #[clap(long, multiple_occurrences = true, help = "Print the m/z of this adduct ion instead, e.g. [M+Na]+ or [M+2H]2+")]
+    adduct: Vec<AdductIon>,
+}
+
+/// How many solvate fits the composition command prints
+const SOLVATE_FITS: usize = 5;
+
+#[derive(Args)]
+struct CompositionArgs {
+    #[clap(help = "Formula, e.g. C8H9NO2")]
+    formula: String,
+    #[clap(help = "Percentages found by elemental analysis, e.g. C=63.4 H=6.1 N=9.3")]
+    #[clap(parse(try_from_str = parse_element_percent))]
+    found: Vec<(Element, f64)>,
+    #[clap(long, default_value_t = ANALYSIS_TOLERANCE, help = "Largest deviation that passes, in percentage points")]
+    tolerance: f64,
+    #[clap(long, requires = "found", help = "Rank solvates of common solvents, e.g. ·0.5H2O, by fit to the analysis")]
+    solvates: bool,
+    #[clap(long, multiple_occurrences = true, requires = "found", help = "Rank solvates of this solvent instead, e.g. CH2Cl2")]
+    solvent: Vec<String>,
+    #[clap(long, default_value = "0.25", help = "Step between solvent counts")]
+    #[clap(parse(try_from_str = parse::parse_coefficient))]
+    step: Rational,
+    #[clap(long, default_value = "2", help = "Largest solvent count")]
+    #[clap(parse(try_from_str = parse::parse_coefficient))]
+    max_solvent: Rational,
+}
+
+#[derive(Args)]
+struct EmpiricalArgs {
+    #[clap(required = true, help = "Percent by mass of each element, e.g. C=40.0 H=6.71 O=53.3")]
+    #[clap(parse(try_from_str = parse_element_percent))]
+    percents: Vec<(Element, f64)>,
+    #[clap(long, default_value_t = ANALYSIS_TOLERANCE, help = "Largest deviation of a formula from the percentages, in percentage points")]
+    tolerance: f64,
+    #[clap(long, help = "Molar mass (g/mol) for the molecular formula, e.g. 180")]
+    molar_mass: Option<f64>,
+}
+
+/// Parses an element's percent by mass, e.g. C=40.0
+fn parse_element_percent(arg: &str) -> Result<(Element, f64), String> {
+    let invalid = || format!("Invalid element percentage {:?}, expected e.g. C=40.0", arg);
+    let (symbol, percent) = arg.split_once('=').ok_or_else(invalid)?;
+    let element = Element::from_symbol(symbol.trim()).ok_or_else(invalid)?;
+    match percent.trim().parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok((element, percent)),
+        _ => Err(invalid()),
+    }
 }
 
-fn print_result_list(list: Vec<(&Compound, f32)>, units: Units) {
+fn pass_or_fail(analysis: &ElementalAnalysis, tolerance: f64) -> &'static str {
+    match analysis.passes(tolerance) {
+        true => "PASS",
+        false => "FAIL",
+    }
+}
+
+fn print_balancings(balancings: &Balancings, explicit: bool) {
+    println!("{} independent balancing(s):", balancings.dimension());
+    balancings.basis.iter().for_each(|b| {
+        println!("  {}", b.display_string(explicit))
+    });
+    println!("Minimal balancings with species on their given sides:");
+    balancings.minimal.iter().for_each(|b| {
+        println!("  {}", b.display_string(explicit))
+    });
+}
+
+fn print_moved_species(unordered: &UnorderedBalance) {
+    unordered.moved.iter().for_each(|c| {
+        println!("WARNING: {} belongs on the other side of the equation", c.formula)
+    });
+}
+
+fn print_redox_balance(balanced: &RedoxBalance, explicit: bool) {
+    println!("Oxidation: {}", balanced.oxidation.display_string(explicit));
+    println!("Reduction: {}", balanced.reduction.display_string(explicit));
+    println!("Overall: {}", balanced.overall.display_string(explicit));
+    if !balanced.spectators.is_empty() {
+        let formulas: Vec<&str> = balanced
+            .spectators
+            .iter()
+            .map(|c| c.formula.as_str())
+            .collect();
+        println!("WARNING: left out spectators {}", formulas.join(", "));
+    }
+}
+
+fn print_result_list(list: Vec<(&Compound, f64)>, units: Units) {
     list.iter().for_each(|(product, yld)| {
-        println!("{} {} {}", product.formula, yld, units)
+        println!("{} {} {}", product.formula, number(*yld), units)
     })
 }
+
+/// Decimal places for masses, moles and yields, or None for all digits
+static PRECISION: RwLock<Option<usize>> = RwLock::new(None);
+
+/// Writes a mass, amount or yield with the chosen --precision
+fn number(value: f64) -> String {
+    match *PRECISION.read().expect("Precision poisoned") {
+        Some(places) => format!("{:.*}", places, value),
+        None => value.to_string(),
+    }
+}
+
+fn print_atomic_weights() {
+    println!("Atomic weights: {}", model::atomic_weights_source())
+}
@@ -1,4 +1,5 @@
 use crate::model::{Compound, Reaction};
+use crate::parse;
 use serde::Deserialize;
 use std::fs::read_to_string;
 
@@ -9,7 +10,7 @@ struct ChemdrawReaction {
 }
 
 impl ChemdrawReaction {
-    pub fn reactants(&self) -> Vec<String> {
+    pub fn reactants(&self) -> Result<Vec<Compound>, String> {
         self.steps
             .first()
             .map(|s: &Step| {
@@ -20,17 +21,17 @@ impl ChemdrawReaction {
             })
             .unwrap_or_default()
             .iter()
-            .map(|m: &&Molecule| m.formula())
+            .map(|m: &&Molecule| m.compound())
             .collect()
     }
 
-    pub fn products(&self) -> Vec<String> {
+    pub fn products(&self) -> Result<Vec<Compound>, String> {
         self.steps
             .first()
             .map(|s| &s.products)
             .unwrap_or(&Vec::new())
             .iter()
-            .map(|m| m.formula())
+            .map(|m| m.compound())
             .collect()
     }
 }
@@ -45,21 +46,81 @@ struct Step {
     products: Vec<Molecule>,
 }
 
+/// How far, in daltons, an EM may be from the monoisotopic mass
+const EXACT_MASS_TOLERANCE: f64 = 0.001;
+
 #[derive(Deserialize, Debug)]
 struct Molecule {
-    #[allow(dead_code)]
     #[serde(rename(deserialize = "NAME"))]
     name: String,
-    #[serde(rename(deserialize = "FORMULA"))]
+    #[serde(rename(deserialize = "FORMULA"), default)]
     raw_formula: String,
-    #[allow(dead_code)]
-    #[serde(rename(deserialize = "SMILES"))]
+    #[serde(rename(deserialize = "INCHI"), default)]
+    inchi: String,
+    #[serde(rename(deserialize = "EM"), default)]
+    exact_mass: Option<f64>,
+    #[serde(rename(deserialize = "SMILES"), default)]
     smiles: String,
 }
 
 impl Molecule {
     pub fn formula(&self) -> String {
-        self.raw_formula.replace("<sub>", "").replace("</sub>", "")
+        parse::normalize(&self.raw_formula)
+    }
+
+    /// The compound of its InChI when it has one, written as its formula
+    /// when that agrees. It must have the monoisotopic mass of its EM, and
+    /// the atoms of its SMILES when that can be read.
+    pub fn compound(&self) -> Result<Compound, String> {
+        let formula = Compound::from_formula(&self.formula());
+        let compound = match (self.inchi.is_empty(), formula) {
+            (true, formula) => formula?,
+            (false, formula) => {
+                let inchi = Compound::from_formula(&self.inchi)?;
+                match formula {
+                    Ok(formula)
+                        if formula.atoms == inchi.atoms
+                            && formula.charge == inchi.charge =>
+                    {
+                        formula
+                    }
+                    _ => {
+                        warn!(
+                            "Using the InChI of {} over its formula {}",
+                            self.name, self.raw_formula
+                        );
+                        inchi
+                    }
+                }
+            }
+        };
+        if let (Some(exact_mass), Ok(mass)) =
+            (self.exact_mass, compound.monoisotopic_mass())
+        {
+            if (exact_mass - mass).abs() > EXACT_MASS_TOLERANCE {
+                return Err(format!(
+                    "Exact mass {} of {} does not match {} ({:.6})",
+                    exact_mass, self.name, compound.formula, mass
+                ));
+            }
+        }
+        if self.smiles.is_empty() {
+            return Ok(compound);
+        }
+        match parse::parse_smiles(&self.smiles) {
+            Ok(smiles) if smiles.atoms != compound.atoms => Err(format!(
+                "Formula {} of {} does not match its SMILES {} ({})",
+                compound.formula, self.name, self.smiles, smiles.base
+            )),
+            Ok(_) => Ok(compound),
+            Err(e) => {
+                warn!(
+                    "Could not check {} against its SMILES: {}",
+                    self.name, e
+                );
+                Ok(compound)
+            }
+        }
     }
 }
 
@@ -86,19 +147,9 @@ pub fn parse_chemdraw_reaction(
     let parsed: Vec<ChemdrawReaction> = serde_json::from_str(document)
         .map_err(|e| format!("Could not parse: {:?}", e))?;
     let rxn = parsed.first().ok_or_else(|| "No reactions!".to_string())?;
-    let reactants: Vec<Compound> = rxn
-        .reactants()
-        .iter()
-        .map(|r| Compound::from_formula(r))
-        .collect::<Result<Vec<Compound>, String>>()?;
-    let products: Vec<Compound> = rxn
-        .products()
-        .iter()
-        .map(|r| Compound::from_formula(r))
-        .collect::<Result<Vec<Compound>, String>>()?;
     Ok(ParsedReaction {
-        reactants,
-        products,
+        reactants: rxn.reactants()?,
+        products: rxn.products()?,
     })
 }
 
@@ -119,4 +170,54 @@ mod tests {
         assert_eq!(result.products.len(), 1);
         assert_eq!(result.products.first().unwrap().formula, "CH4");
     }
+
+    #[test]
+    fn test_smiles_mismatch() {
+        let document = r#"[{
+"STEPS":[{
+"REACTANTS":[{"ID":"54","NAME":"dimethylamine","FORMULA":"C<sub>2</sub>H<sub>6</sub>N","SMILES":"CNC"}],
+"REAGENTS":[],
+"PRODUCTS":[]}]}]
+"#;
+        let error = parse_chemdraw_reaction(document).unwrap_err();
+        assert_eq!(
+            error,
+            "Formula C2H6N of dimethylamine does not match its SMILES CNC (C2H7N)"
+        );
+    }
+
+    #[test]
+    fn test_inchi_over_formula() {
+        let document = r#"[{
+"STEPS":[{
+"REACTANTS":[{"ID":"54","NAME":"sodium acetate","FORMULA":"","INCHI":"InChI=1S/C2H4O2.Na/c1-2(3)4;/h1H3,(H,3,4);/q;+1/p-1","SMILES":"CC(=O)[O-].[Na+]"}],
+"REAGENTS":[],
+"PRODUCTS":[{"ID":"59","NAME":"methane","FORMULA":"CH<sub>3</sub>","INCHI":"InChI=1S/CH4/h1H4","SMILES":"C"}]}]}]
+"#;
+        let result = parse_chemdraw_reaction(document).unwrap();
+        let acetate = result.reactants.first().unwrap();
+        assert_eq!(acetate.formula, "C2H3NaO2");
+        assert_eq!(
+            acetate.inchi.as_deref(),
+            Some("InChI=1S/C2H4O2.Na/c1-2(3)4;/h1H3,(H,3,4);/q;+1/p-1")
+        );
+        assert_eq!(acetate.charge, 0);
+        let methane = result.products.first().unwrap();
+        assert_eq!(methane.base, "CH4");
+    }
+
+    #[test]
+    fn test_exact_mass_mismatch() {
+        let document = r#"[{
+"STEPS":[{
+"REACTANTS":[{"ID":"54","NAME":"methane","FORMULA":"CH<sub>4</sub>","EM":17.0346,"SMILES":"C"}],
+"REAGENTS":[],
+"PRODUCTS":[]}]}]
+"#;
+        let error = parse_chemdraw_reaction(document).unwrap_err();
+        assert_eq!(
+            error,
+            "Exact mass 17.0346 of methane does not match CH4 (16.031300)"
+        );
+    }
 }
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::RwLock;
+
+use clap::ArgEnum;
+
+use crate::model::Element;
+
+/// A source of atomic weights for elements without an isotope label
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtomicWeights {
+    /// The PubChem periodic table values bundled with the element data
+    Pubchem,
+    /// IUPAC standard atomic weights, taking the midpoint of each interval
+    Standard,
+    /// IUPAC standard atomic weights, with the conventional value for
+    /// elements whose weight is an interval
+    Conventional,
+    /// IUPAC atomic weights abridged to five significant figures
+    Abridged,
+}
+
+impl Display for AtomicWeights {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AtomicWeights::Pubchem => write!(f, "PubChem atomic weights"),
+            AtomicWeights::Standard => write!(
+                f,
+                "IUPAC 2021 standard atomic weights (interval midpoints)"
+            ),
+            AtomicWeights::Conventional => {
+                write!(f, "IUPAC 2021 conventional atomic weights")
+            }
+            AtomicWeights::Abridged => {
+                write!(f, "IUPAC 2021 abridged atomic weights")
+            }
+        }
+    }
+}
+
+/// An IUPAC standard atomic weight, or the interval that the weight of
+/// normal materials falls in along with its conventional value
+#[derive(Clone, Copy)]
+enum Weight {
+    Value(f64),
+    Interval(f64, f64, f64),
+}
+
+use Weight::{Interval, Value};
+
+/// IUPAC (CIAAW 2021) atomic weights and their abridged values. Elements
+/// without stable isotopes have no standard weight and are left out.
+#[rustfmt::skip]
+const IUPAC: &[(&str, Weight, f64)] = &[
+    ("H", Interval(1.00784, 1.00811, 1.008), 1.0080),
+    ("He", Value(4.002602), 4.0026),
+    ("Li", Interval(6.938, 6.997, 6.94), 6.94),
+    ("Be", Value(9.0121831), 9.0122),
+    ("B", Interval(10.806, 10.821, 10.81), 10.81),
+    ("C", Interval(12.0096, 12.0116, 12.011), 12.011),
+    ("N", Interval(14.00643, 14.00728, 14.007), 14.007),
+    ("O", Interval(15.99903, 15.99977, 15.999), 15.999),
+    ("F", Value(18.998403162), 18.998),
+    ("Ne", Value(20.1797), 20.180),
+    ("Na", Value(22.98976928), 22.990),
+    ("Mg", Interval(24.304, 24.307, 24.305), 24.305),
+    ("Al", Value(26.9815384), 26.982),
+    ("Si", Interval(28.084, 28.086, 28.085), 28.085),
+    ("P", Value(30.973761998), 30.974),
+    ("S", Interval(32.059, 32.076, 32.06), 32.06),
+    ("Cl", Interval(35.446, 35.457, 35.45), 35.45),
+    ("Ar", Interval(39.792, 39.963, 39.95), 39.95),
+    ("K", Value(39.0983), 39.098),
+    ("Ca", Value(40.078), 40.078),
+    ("Sc", Value(44.955907), 44.956),
+    ("Ti", Value(47.867), 47.867),
+    ("V", Value(50.9415), 50.942),
+    ("Cr", Value(51.9961), 51.996),
+    ("Mn", Value(54.938043), 54.938),
+    ("Fe", Value(55.845), 55.845),
+    ("Co", Value(58.933194), 58.933),
+    ("Ni", Value(58.6934), 58.693),
+    ("Cu", Value(63.546), 63.546),
+    ("Zn", Value(65.38), 65.38),
+    ("Ga", Value(69.723), 69.723),
+    ("Ge", Value(72.630), 72.630),
+    ("As", Value(74.921595), 74.922),
+    ("Se", Value(78.971), 78.971),
+    ("Br", Interval(79.901, 79.907, 79.904), 79.904),
+    ("Kr", Value(83.798), 83.798),
+    ("Rb", Value(85.4678), 85.468),
+    ("Sr", Value(87.62), 87.62),
+    ("Y", Value(88.905838), 88.906),
+    ("Zr", Value(91.222), 91.222),
+    ("Nb", Value(92.90637), 92.906),
+    ("Mo", Value(95.95), 95.95),
+    ("Ru", Value(101.07), 101.07),
+    ("Rh", Value(102.90549), 102.91),
+    ("Pd", Value(106.42), 106.42),
+    ("Ag", Value(107.8682), 107.87),
+    ("Cd", Value(112.414), 112.41),
+    ("In", Value(114.818), 114.82),
+    ("Sn", Value(118.710), 118.71),
+    ("Sb", Value(121.760), 121.76),
+    ("Te", Value(127.60), 127.60),
+    ("I", Value(126.90447), 126.90),
+    ("Xe", Value(131.293), 131.29),
+    ("Cs", Value(132.90545196), 132.91),
+    ("Ba", Value(137.327), 137.33),
+    ("La", Value(138.90547), 138.91),
+    ("Ce", Value(140.116), 140.12),
+    ("Pr", Value(140.90766), 140.91),
+    ("Nd", Value(144.242), 144.24),
+    ("Sm", Value(150.36), 150.36),
+    ("Eu", Value(151.964), 151.96),
+    ("Gd", Value(157.249), 157.25),
+    ("Tb", Value(158.925354), 158.93),
+    ("Dy", Value(162.500), 162.50),
+    ("Ho", Value(164.930329), 164.93),
+    ("Er", Value(167.259), 167.26),
+    ("Tm", Value(168.934219), 168.93),
+    ("Yb", Value(173.045), 173.05),
+    ("Lu", Value(174.9668), 174.97),
+    ("Hf", Value(178.486), 178.49),
+    ("Ta", Value(180.94788), 180.95),
+    ("W", Value(183.84), 183.84),
+    ("Re", Value(186.207), 186.21),
+    ("Os", Value(190.23), 190.23),
+    ("Ir", Value(192.217), 192.22),
+    ("Pt", Value(195.084), 195.08),
+    ("Au", Value(196.966570), 196.97),
+    ("Hg", Value(200.592), 200.59),
+    ("Tl", Interval(204.382, 204.385, 204.38), 204.38),
+    ("Pb", Interval(206.14, 207.94, 207.2), 207.2),
+    ("Bi", Value(208.98040), 208.98),
+    ("Th", Value(232.0377), 232.04),
+    ("Pa", Value(231.03588), 231.04),
+    ("U", Value(238.02891), 238.03),
+];
+
+struct Source {
+    table: AtomicWeights,
+    overrides: BTreeMap<String, f64>,
+    files: Vec<String>,
+}
+
+static SOURCE: RwLock<Source> = RwLock::new(Source {
+    table: AtomicWeights::Pubchem,
+    overrides: BTreeMap::new(),
+    files: vec![],
+});
+
+/// Chooses the table of atomic weights used for molar masses
+pub fn set_atomic_weights(table: AtomicWeights) {
+    SOURCE.write().expect("Atomic weights poisoned").table = table;
+}
+
+/// Overrides the atomic weight of an element, e.g. Li in an enriched
+/// material, whichever table is chosen
+pub fn set_atomic_weight(symbol: &str, weight: f64) -> Result<(), String> {
+    match Element::from_symbol(symbol) {
+        Some(element) if element.mass_number().is_none() => {}
+        _ => return Err(format!("Invalid element {:?}", symbol)),
+    }
+    if !(weight.is_finite() && weight > 0.0) {
+        return Err(format!("Invalid atomic weight {} for {}", weight, symbol));
+    }
+    debug!("Overriding atomic weight of {} with {}", symbol, weight);
+    SOURCE
+        .write()
+        .expect("Atomic weights poisoned")
+        .overrides
+        .insert(symbol.to_string(), weight);
+    Ok(())
+}
+
+/// Overrides atomic weights from a JSON object file, e.g. {"Li": 6.94},
+/// returning how many were set
+pub fn load_atomic_weights(path: &str) -> Result<usize, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let weights: BTreeMap<String, f64> = serde_json::from_str(&contents)
+        .map_err(|e| format!("Could not parse {}: {}", path, e))?;
+    weights
+        .iter()
+        .try_for_each(|(symbol, weight)| set_atomic_weight(symbol, *weight))?;
+    SOURCE
+        .write()
+        .expect("Atomic weights poisoned")
+        .files
+        .push(path.to_string());
+    Ok(weights.len())
+}
+
+/// Describes the atomic weights in use, e.g. for reporting with results
+pub fn atomic_weights_source() -> String {
+    let source = SOURCE.read().expect("Atomic weights poisoned");
+    if source.overrides.is_empty() {
+        return source.table.to_string();
+    }
+    let symbols: Vec<&str> =
+        source.overrides.keys().map(String::as_str).collect();
+    match source.files.is_empty() {
+        true => format!("{}, {} overridden", source.table, symbols.join(", ")),
+        false => format!(
+            "{}, {} overridden from {}",
+            source.table,
+            symbols.join(", "),
+            source.files.join(", ")
+        ),
+    }
+}
+
+/// The weight of an element in a table, or None if it has no IUPAC weight
+fn table_weight(table: AtomicWeights, symbol: &str) -> Option<f64> {
+    let (_, weight, abridged) =
+        IUPAC.iter().find(|(iupac, _, _)| *iupac == symbol)?;
+    match (table, weight) {
+        (AtomicWeights::Pubchem, _) => None,
+        (AtomicWeights::Abridged, _) => Some(*abridged),
+        (_, Value(value)) => Some(*value),
+        (AtomicWeights::Standard, Interval(low, high, _)) => {
+            Some((low + high) / 2.0)
+        }
+        (_, Interval(_, _, conventional)) => Some(*conventional),
+    }
+}
+
+/// The atomic weight of an element in the chosen table, after overrides.
+/// Elements without an IUPAC weight, such as Tc, use the PubChem value.
+pub(crate) fn atomic_weight(symbol: &str, pubchem: f32) -> f64 {
+    let source = SOURCE.read().expect("Atomic weights poisoned");
+    source
+        .overrides
+        .get(symbol)
+        .copied()
+        .or_else(|| table_weight(source.table, symbol))
+        // the shortest decimal for the f32, e.g. 12.011 rather than
+        // 12.01099967956543
+        .unwrap_or_else(|| pubchem.to_string().parse().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::atomic_weights::{
+        set_atomic_weight, table_weight, AtomicWeights,
+    };
+
+    #[test]
+    fn test_tables() {
+        let weight = |table, symbol| table_weight(table, symbol).unwrap();
+        assert_eq!(weight(AtomicWeights::Standard, "Fe"), 55.845);
+        assert_eq!(weight(AtomicWeights::Conventional, "Li"), 6.94);
+        assert!((weight(AtomicWeights::Standard, "B") - 10.8135).abs() < 1e-9);
+        assert_eq!(weight(AtomicWeights::Abridged, "Na"), 22.990);
+        assert_eq!(table_weight(AtomicWeights::Standard, "Tc"), None);
+        assert_eq!(table_weight(AtomicWeights::Pubchem, "Fe"), None);
+    }
+
+    #[test]
+    fn test_invalid_overrides() {
+        assert!(set_atomic_weight("Xx", 1.0).is_err());
+        assert!(set_atomic_weight("D", 2.014).is_err());
+        assert!(set_atomic_weight("Li", -6.94).is_err());
+        assert!(set_atomic_weight("Li", f64::NAN).is_err());
+    }
+}
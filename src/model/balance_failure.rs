@@ -0,0 +1,275 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+use rug::Rational;
+
+use crate::solve;
+use crate::solve::{RowLabel, StoichiometricMatrix};
+
+/// Why a reaction could not be balanced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailureKind {
+    /// Only the all-zero solution exists
+    NoSolution,
+    /// Every solution gives some species a zero coefficient
+    ZeroCoefficients,
+    /// The solution space has more than one dimension
+    Ambiguous(usize),
+    /// The only solution moves some species to the other side
+    WrongSides,
+    /// The solution did not check out, e.g. a species with no atoms
+    Invalid(String),
+}
+
+impl Display for FailureKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailureKind::NoSolution => {
+                write!(f, "only the all-zero solution exists")
+            }
+            FailureKind::ZeroCoefficients => {
+                write!(f, "some species must have a 0 coefficient")
+            }
+            FailureKind::Ambiguous(n) => write!(
+                f,
+                "there are {} independent solutions, cannot choose one",
+                n
+            ),
+            FailureKind::WrongSides => {
+                write!(f, "some species are on the wrong side")
+            }
+            FailureKind::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// A structured explanation of why `Reaction::balance` failed
+#[derive(Debug, Clone)]
+pub struct BalanceFailure {
+    pub kind: FailureKind,
+    /// Element (or charge) rows that cannot be conserved by the species
+    pub inconsistent_rows: Vec<RowLabel>,
+    /// Species whose coefficient is 0 in every solution
+    pub zero_species: Vec<String>,
+    /// Species whose composition is a multiple of an earlier species
+    pub redundant_species: Vec<String>,
+    /// Smallest groups of species that balance among themselves
+    pub dependent_species: Vec<Vec<String>>,
+    /// Species the solution places on the other side
+    pub misplaced_species: Vec<String>,
+    pub suggestions: Vec<String>,
+}
+
+impl BalanceFailure {
+    pub fn new(kind: FailureKind) -> BalanceFailure {
+        BalanceFailure {
+            kind,
+            inconsistent_rows: vec![],
+            zero_species: vec![],
+            redundant_species: vec![],
+            dependent_species: vec![],
+            misplaced_species: vec![],
+            suggestions: vec![],
+        }
+    }
+
+    /// Analyses the stoichiometric matrix of a reaction that could not be
+    /// balanced
+    pub(crate) fn diagnose(mx: &StoichiometricMatrix) -> BalanceFailure {
+        let ncols = mx.ncols();
+        let names = &mx.columns;
+        let basis = mx.nullspace();
+        let zeroes = zero_columns(&basis, ncols);
+        let kind = match (basis.len(), zeroes.is_empty()) {
+            (0, _) => FailureKind::NoSolution,
+            (_, false) => FailureKind::ZeroCoefficients,
+            (1, true) => FailureKind::WrongSides,
+            (n, true) => FailureKind::Ambiguous(n),
+        };
+        debug!("Diagnosing failure: {:?}", kind);
+        let mut failure = BalanceFailure::new(kind);
+        failure.zero_species =
+            zeroes.iter().map(|&c| names[c].clone()).collect();
+        failure.redundant_species = redundant_columns(&mx.entries, ncols)
+            .into_iter()
+            .map(|c| names[c].clone())
+            .collect();
+        match failure.kind {
+            FailureKind::NoSolution | FailureKind::ZeroCoefficients => {
+                for (label, row) in mx.rows.iter().zip(mx.entries.iter()) {
+                    let freed =
+                        zero_columns(&mx.without_row(label).nullspace(), ncols);
+                    if freed.len() < zeroes.len() {
+                        failure.inconsistent_rows.push(*label);
+                        failure.suggestions.extend(suggest_for_row(label, row));
+                    }
+                }
+                if failure.suggestions.is_empty()
+                    && !failure.inconsistent_rows.is_empty()
+                {
+                    failure.suggestions.push(format!(
+                        "check the formulas, no combination of species conserves {} together",
+                        failure.inconsistent_rows.iter().join(", ")
+                    ));
+                }
+                // removing every species is no suggestion at all
+                zeroes
+                    .iter()
+                    .filter(|_| zeroes.len() < ncols)
+                    .for_each(|&c| {
+                        failure.suggestions.push(format!(
+                            "remove {}, it cannot take part in the reaction",
+                            names[c]
+                        ))
+                    });
+            }
+            FailureKind::Ambiguous(_) => {
+                match solve::dependent_sets(&mx.entries, ncols) {
+                    Ok(sets) => {
+                        failure.dependent_species = sets
+                            .into_iter()
+                            .map(|set| {
+                                set.into_iter()
+                                    .map(|c| names[c].clone())
+                                    .collect()
+                            })
+                            .collect()
+                    }
+                    Err(_) => failure.suggestions.push(format!(
+                        "too many species to diagnose which balance among themselves, at most {}",
+                        solve::MAX_SUPPORT_COLUMNS
+                    )),
+                }
+                for (c, name) in names.iter().enumerate() {
+                    let remaining = mx.without_column(c).nullspace();
+                    if remaining.len() == 1
+                        && zero_columns(&remaining, ncols - 1).is_empty()
+                    {
+                        failure.suggestions.push(format!(
+                            "remove {} to get a single balancing",
+                            name
+                        ));
+                    }
+                }
+                failure.suggestions.push(
+                    "use balance --all to list every balancing".to_string(),
+                );
+            }
+            FailureKind::WrongSides => {
+                let integers = solve::scale_to_integers(&basis[0]);
+                let positive =
+                    integers.iter().filter(|i| i.cmp0() == Ordering::Greater);
+                let negative =
+                    integers.iter().filter(|i| i.cmp0() == Ordering::Less);
+                let minority = match positive.count() >= negative.count() {
+                    true => Ordering::Less,
+                    false => Ordering::Greater,
+                };
+                failure.misplaced_species = integers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, i)| i.cmp0() == minority)
+                    .map(|(c, _)| names[c].clone())
+                    .collect();
+                failure.misplaced_species.iter().for_each(|name| {
+                    let side = match names.iter().position(|n| n == name) {
+                        Some(c) if c < mx.reactant_count => "products",
+                        _ => "reactants",
+                    };
+                    failure
+                        .suggestions
+                        .push(format!("move {} to the {}", name, side))
+                });
+                failure.suggestions.push(
+                    "use balance --auto-sides to place species automatically"
+                        .to_string(),
+                );
+            }
+            FailureKind::Invalid(_) => (),
+        }
+        failure
+    }
+}
+
+impl Display for BalanceFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Equation cannot be balanced: {}", self.kind)?;
+        if !self.inconsistent_rows.is_empty() {
+            let rows = self.inconsistent_rows.iter().join(", ");
+            write!(f, "\nInconsistent rows: {}", rows)?;
+        }
+        let lists = [
+            ("Species with a 0 coefficient", &self.zero_species),
+            ("Redundant species", &self.redundant_species),
+            ("Species on the wrong side", &self.misplaced_species),
+        ];
+        for (title, list) in lists.iter().filter(|(_, l)| !l.is_empty()) {
+            write!(f, "\n{}: {}", title, list.join(", "))?;
+        }
+        if !self.dependent_species.is_empty() {
+            write!(f, "\nGroups of species that balance among themselves:")?;
+            for group in self.dependent_species.iter() {
+                write!(f, "\n  {}", group.join(", "))?;
+            }
+        }
+        if !self.suggestions.is_empty() {
+            write!(f, "\nSuggestions:")?;
+            for suggestion in self.suggestions.iter() {
+                write!(f, "\n  - {}", suggestion)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Box<BalanceFailure>> for String {
+    fn from(failure: Box<BalanceFailure>) -> Self {
+        failure.to_string()
+    }
+}
+
+/// Columns that are 0 in every basis vector, i.e. species that cannot take
+/// part in any balancing. With an empty basis, every column.
+fn zero_columns(basis: &[Vec<Rational>], ncols: usize) -> Vec<usize> {
+    (0..ncols)
+        .filter(|&c| basis.iter().all(|v| v[c].cmp0() == Ordering::Equal))
+        .collect()
+}
+
+/// Columns that are a multiple of an earlier column, e.g. O3 after O2
+fn redundant_columns(mx: &[Vec<Rational>], ncols: usize) -> Vec<usize> {
+    let column = |c: usize| -> Vec<Rational> {
+        mx.iter().map(|row| row[c].clone()).collect()
+    };
+    (0..ncols)
+        .filter(|&c| {
+            (0..c).any(|earlier| {
+                let pair: Vec<Vec<Rational>> = column(earlier)
+                    .into_iter()
+                    .zip(column(c))
+                    .map(|(a, b)| vec![a, b])
+                    .collect();
+                solve::rank(&pair, 2) < 2
+            })
+        })
+        .collect()
+}
+
+/// Suggests a fix for a row that cannot be conserved, based on which side
+/// of the reaction has it. Rows on both sides only conflict with others.
+fn suggest_for_row(label: &RowLabel, row: &[Rational]) -> Option<String> {
+    let on_reactants = row.iter().any(|v| v.cmp0() == Ordering::Greater);
+    let on_products = row.iter().any(|v| v.cmp0() == Ordering::Less);
+    match (label, on_reactants, on_products) {
+        (RowLabel::Charge, _, _) => Some(
+            "add electrons (e-) or counter ions to conserve charge, or use balance --redox"
+                .to_string(),
+        ),
+        (_, true, false) => Some(format!("add a product containing {}", label)),
+        (_, false, true) => {
+            Some(format!("add a reactant containing {}", label))
+        }
+        _ => None,
+    }
+}
@@ -1,7 +1,10 @@
 use std::collections::HashMap;
 use std::slice::Iter;
 
-use crate::model::{ElementCounts, Reactant};
+use rug::Rational;
+
+use crate::model::reactant::ScaledElementCounts;
+use crate::model::{Compound, Reactant};
 
 #[derive(Debug, Clone)]
 pub struct BalancedReaction {
@@ -15,12 +18,19 @@ impl BalancedReaction {
         products: Vec<Reactant>,
     ) -> Result<BalancedReaction, String> {
         match BalancedReaction::check_balance(&reactants, &products) {
+            Ok(_) => Ok(()),
+            Err((reactants, products)) => Err(format!(
+                "Equation is not be balanced\n{:?}\n{:?}",
+                reactants, products
+            )),
+        }?;
+        match BalancedReaction::check_charge(&reactants, &products) {
             Ok(_) => Ok(BalancedReaction {
                 reactants,
                 products,
             }),
             Err((reactants, products)) => Err(format!(
-                "Equation is not be balanced\n{:?}\n{:?}",
+                "Equation charge is not balanced\n{} != {}",
                 reactants, products
             )),
         }
@@ -29,9 +39,9 @@ impl BalancedReaction {
     fn check_balance(
         reactants: &[Reactant],
         products: &[Reactant],
-    ) -> Result<(), (ElementCounts, ElementCounts)> {
-        let react_elems: ElementCounts = Reactant::element_counts(reactants);
-        let prod_elems: ElementCounts = Reactant::element_counts(products);
+    ) -> Result<(), (ScaledElementCounts, ScaledElementCounts)> {
+        let react_elems = Reactant::element_counts(reactants);
+        let prod_elems = Reactant::element_counts(products);
         debug!(
             "Checking balanced?: Reagent elements: {:?} === Product elements: {:?}",
             react_elems, prod_elems
@@ -42,44 +52,106 @@ impl BalancedReaction {
         }
     }
 
-    fn reactants_display_string(&self, explicit: bool) -> String {
-        let balanced: Vec<String> =
-            self.reactants.iter().map(|r| r.format(explicit)).collect();
-        balanced.join(" + ")
+    fn check_charge(
+        reactants: &[Reactant],
+        products: &[Reactant],
+    ) -> Result<(), (Rational, Rational)> {
+        let react_charge = Reactant::total_charge(reactants);
+        let prod_charge = Reactant::total_charge(products);
+        debug!(
+            "Checking balanced?: Reagent charge: {} === Product charge: {}",
+            react_charge, prod_charge
+        );
+        match react_charge == prod_charge {
+            true => Ok(()),
+            false => Err((react_charge, prod_charge)),
+        }
+    }
+
+    pub fn display_string(&self, explicit: bool) -> String {
+        self.display_with(|r| r.format(explicit))
     }
 
-    fn products_display_string(&self, explicit: bool) -> String {
-        let balanced: Vec<String> =
-            self.products.iter().map(|r| r.format(explicit)).collect();
-        balanced.join(" + ")
+    /// Displays the reaction with decimal coefficients, e.g.
+    /// C8H18 + 12.5*O2 = 8*CO2 + 9*H2O
+    pub fn decimal_display_string(&self, explicit: bool) -> String {
+        self.display_with(|r| r.format_decimal(explicit))
     }
 
-    pub fn display_string(&self, explicit: bool) -> String {
-        format!(
-            "{} = {}",
-            self.reactants_display_string(explicit),
-            self.products_display_string(explicit)
-        )
+    fn display_with(&self, format: impl Fn(&Reactant) -> String) -> String {
+        let side = |reactants: &[Reactant]| {
+            reactants
+                .iter()
+                .map(&format)
+                .collect::<Vec<String>>()
+                .join(" + ")
+        };
+        format!("{} = {}", side(&self.reactants), side(&self.products))
+    }
+
+    /// The coefficient of the given species, if it takes part in the
+    /// reaction
+    pub fn coefficient_of(&self, compound: &Compound) -> Option<&Rational> {
+        self.reactants
+            .iter()
+            .chain(self.products.iter())
+            .find(|r| r.compound.same_species(compound))
+            .map(|r| &r.molar_coefficient)
+    }
+
+    /// Scales the reaction so the pinned species has the given (possibly
+    /// fractional) coefficient
+    pub fn per(
+        &self,
+        pinned: &Compound,
+        coefficient: impl Into<Rational>,
+    ) -> Result<BalancedReaction, String> {
+        let current = self.coefficient_of(pinned).ok_or_else(|| {
+            format!("{} is not part of the reaction", pinned.formula)
+        })?;
+        let factor = coefficient.into() / current;
+        debug!("Scaling balanced reaction by {}", factor);
+        Ok(self.scale(&factor))
+    }
+
+    /// Multiplies every coefficient by the given factor
+    pub fn scale(&self, factor: &Rational) -> BalancedReaction {
+        let scale_side = |reactants: &[Reactant]| {
+            reactants
+                .iter()
+                .map(|r| {
+                    Reactant::of_compound(
+                        r.compound.clone(),
+                        Rational::from(&r.molar_coefficient * factor),
+                    )
+                })
+                .collect()
+        };
+        BalancedReaction {
+            reactants: scale_side(&self.reactants),
+            products: scale_side(&self.products),
+        }
     }
 
     #[allow(dead_code)]
-    pub(crate) fn all_coefficients(&self) -> Vec<usize> {
+    pub(crate) fn all_coefficients(&self) -> Vec<Rational> {
         self.reactants
             .iter()
             .chain(self.products.iter())
-            .map(|r| r.molar_coefficient)
+            .map(|r| r.molar_coefficient.clone())
             .collect()
     }
 }
 
-struct ReactantMap(HashMap<String, usize>);
+/// The coefficients of one side by species, adding up any species listed
+/// more than once
+struct ReactantMap(HashMap<Compound, Rational>);
 
 impl From<std::slice::Iter<'_, Reactant>> for ReactantMap {
     fn from(reactants: Iter<'_, Reactant>) -> Self {
-        let mut m = HashMap::new();
+        let mut m: HashMap<Compound, Rational> = HashMap::new();
         for r in reactants {
-            m.entry(r.compound.formula.clone())
-                .or_insert(r.molar_coefficient);
+            *m.entry(r.compound.clone()).or_default() += &r.molar_coefficient;
         }
         Self(m)
     }
@@ -94,3 +166,42 @@ impl PartialEq for BalancedReaction {
         r == or && p == op
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rug::Rational;
+
+    use crate::model::{BalancedReaction, Reactant};
+
+    fn reaction(
+        reactants: &[(&str, i32)],
+        products: &[(&str, i32)],
+    ) -> BalancedReaction {
+        let side = |species: &[(&str, i32)]| {
+            species
+                .iter()
+                .map(|(formula, coefficient)| {
+                    Reactant::from_formula(formula, *coefficient).unwrap()
+                })
+                .collect()
+        };
+        BalancedReaction::new(side(reactants), side(products)).unwrap()
+    }
+
+    #[test]
+    fn test_equality() {
+        let written = reaction(
+            &[("C6H5COOH", 2), ("O2", 15)],
+            &[("CO2", 14), ("H2O", 6)],
+        );
+        let hill =
+            reaction(&[("O2", 15), ("C7H6O2", 2)], &[("H2O", 6), ("CO2", 14)]);
+        assert_eq!(written, hill);
+        let repeated = reaction(
+            &[("C7H6O2", 1), ("C6H5COOH", 1), ("O2", 15)],
+            &[("CO2", 14), ("H2O", 6)],
+        );
+        assert_eq!(written, repeated);
+        assert_ne!(written, written.scale(&Rational::from(2)));
+    }
+}
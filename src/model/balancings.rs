@@ -0,0 +1,50 @@
+use crate::model::{BalancedReaction, Compound};
+
+/// Every way to balance a reaction whose solution space has more than one
+/// dimension.
+#[derive(Debug, Clone)]
+pub struct Balancings {
+    /// Independent balanced sub-reactions spanning the solution space.
+    /// A species may appear on the opposite side from where it was given.
+    pub basis: Vec<BalancedReaction>,
+    /// The smallest sub-reactions with every species on its given side.
+    pub minimal: Vec<BalancedReaction>,
+}
+
+impl Balancings {
+    pub fn new(
+        basis: Vec<BalancedReaction>,
+        minimal: Vec<BalancedReaction>,
+    ) -> Balancings {
+        Balancings { basis, minimal }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.basis.len()
+    }
+
+    pub fn is_ambiguous(&self) -> bool {
+        self.dimension() > 1
+    }
+}
+
+/// A balancing where the solution decided which side each species is on.
+#[derive(Debug, Clone)]
+pub struct UnorderedBalance {
+    pub balanced: BalancedReaction,
+    /// Species placed on the opposite side from where they were given.
+    pub moved: Vec<Compound>,
+}
+
+impl UnorderedBalance {
+    pub fn new(
+        balanced: BalancedReaction,
+        moved: Vec<Compound>,
+    ) -> UnorderedBalance {
+        UnorderedBalance { balanced, moved }
+    }
+
+    pub fn agrees_with_given_sides(&self) -> bool {
+        self.moved.is_empty()
+    }
+}
@@ -0,0 +1,128 @@
+use std::any::Any;
+use std::panic;
+use std::thread;
+
+use crate::model::BalancedReaction;
+use crate::parse;
+
+/// Balances each equation, e.g. "H2 O2 = H2O", on as many threads as there
+/// are cores. Results keep the order of the input, and a bad equation, even
+/// one that panics, only fails its own result.
+pub fn balance_many<S: AsRef<str> + Sync>(
+    equations: &[S],
+) -> Vec<Result<BalancedReaction, String>> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = equations.len().div_ceil(threads).max(1);
+    debug!(
+        "Balancing {} equations in chunks of {}",
+        equations.len(),
+        chunk_size
+    );
+    thread::scope(|scope| {
+        let handles: Vec<_> = equations
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let handle = scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|equation| {
+                            let equation: &str = equation.as_ref();
+                            panic::catch_unwind(|| balance_equation(equation))
+                                .unwrap_or_else(|payload| {
+                                    Err(panicked(payload))
+                                })
+                        })
+                        .collect::<Vec<_>>()
+                });
+                (chunk.len(), handle)
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|(len, handle)| {
+                handle.join().unwrap_or_else(|payload| {
+                    let error = panicked(payload);
+                    vec![Err(error); len]
+                })
+            })
+            .collect()
+    })
+}
+
+/// The message of a panic, as an error for its equation
+fn panicked(payload: Box<dyn Any + Send>) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown error".to_string());
+    format!("Balancing failed unexpectedly: {}", message)
+}
+
+fn balance_equation(equation: &str) -> Result<BalancedReaction, String> {
+    let reaction = parse::parse_equation(equation)?.reaction()?;
+    Ok(reaction.balance()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balance_many_keeps_order() {
+        let equations = vec![
+            "CO2 H2O = C6H12O6 O2",
+            "H2O NO2 = HNO3",
+            "",
+            "Al HCl = AlCl3 H2",
+            "KClO3 = KClO4 KCl",
+        ];
+        let results = balance_many(&equations);
+        assert_eq!(results.len(), equations.len());
+        let display: Vec<Option<String>> = results
+            .iter()
+            .map(|r| r.as_ref().ok().map(|b| b.display_string(false)))
+            .collect();
+        assert_eq!(
+            display,
+            vec![
+                Some("6*CO2 + 6*H2O = C6H12O6 + 6*O2".to_string()),
+                None,
+                None,
+                Some("2*Al + 6*HCl = 2*AlCl3 + 3*H2".to_string()),
+                Some("4*KClO3 = 3*KClO4 + KCl".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_panic_messages() {
+        let payload = std::panic::catch_unwind(|| panic!("division by zero"))
+            .unwrap_err();
+        assert_eq!(
+            panicked(payload),
+            "Balancing failed unexpectedly: division by zero"
+        );
+        let payload =
+            std::panic::catch_unwind(|| panic!("{} rows", 3)).unwrap_err();
+        assert_eq!(panicked(payload), "Balancing failed unexpectedly: 3 rows");
+    }
+
+    #[test]
+    fn test_balance_many_large_batch() {
+        let equations: Vec<String> = (0..200)
+            .map(|i| match i % 2 {
+                0 => "H2 O2 = H2O".to_string(),
+                _ => "Na Cl2 = NaCl".to_string(),
+            })
+            .collect();
+        let results = balance_many(&equations);
+        assert!(results.iter().enumerate().all(|(i, r)| {
+            let expected = match i % 2 {
+                0 => "2*H2 + O2 = 2*H2O",
+                _ => "2*Na + Cl2 = 2*NaCl",
+            };
+            r.as_ref().unwrap().display_string(false) == expected
+        }));
+    }
+}
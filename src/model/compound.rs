@@ -1,52 +1,370 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
 
+use rug::Rational;
+
+use crate::model::mass_spec::{self, Peak};
+use crate::model::reactant::decimal_string;
 use crate::model::Element;
 use crate::parse;
+use crate::parse::FormulaError;
+
+pub type ElementCounts = HashMap<Element, Rational>;
 
-pub type ElementCounts = HashMap<Element, usize>;
+/// The elements in Hill order: carbon, hydrogen, then the rest
+/// alphabetically, or all alphabetically without carbon
+fn hill_sorted(atoms: &ElementCounts) -> Vec<&Element> {
+    let mut elements: Vec<&Element> = atoms.keys().collect();
+    let has_carbon = atoms.keys().any(|e| e.get_symbol() == "C");
+    elements.sort_by_key(|e| {
+        let rank = match (has_carbon, e.get_symbol()) {
+            (true, "C") => 0,
+            (true, "H") => 1,
+            _ => 2,
+        };
+        (rank, e.get_symbol(), e.mass_number())
+    });
+    elements
+}
 
+/// Writes atoms in Hill order, e.g. C7H6O2
+pub(crate) fn hill_order(atoms: &ElementCounts) -> String {
+    hill_sorted(atoms)
+        .into_iter()
+        .map(|e| match atoms[e] == 1 {
+            true => e.to_string(),
+            false => format!("{}{}", e, decimal_string(&atoms[e])),
+        })
+        .collect()
+}
+
+/// A compound is identified by its atoms and charge, however its formula is
+/// written, so C6H5COOH equals C7H6O2
 #[derive(Clone, Debug)]
 pub struct Compound {
     pub formula: String,
     pub atoms: ElementCounts,
-    pub molar_mass: f32,
+    /// Computed once, from the atomic weights set when the compound is
+    /// built, so choose the table before reading formulas
+    pub molar_mass: f64,
+    pub charge: i32,
+    /// The formula before any adduct, e.g. CuSO4 in CuSO4·5H2O
+    pub base: String,
+    pub adducts: Vec<Adduct>,
+    /// The InChI the compound was read from, whose formula is written in
+    /// Hill order instead
+    pub inchi: Option<String>,
+}
+
+/// A molecule joined to a compound, e.g. the 5H2O in CuSO4·5H2O
+#[derive(Clone, Debug)]
+pub struct Adduct {
+    pub count: Rational,
+    pub compound: Compound,
 }
 
 impl Compound {
     pub fn from_formula(formula: &str) -> Result<Compound, String> {
-        Compound::new(formula)
+        Ok(Compound::new(formula)?)
     }
 
-    pub fn new(formula: &str) -> Result<Compound, String> {
-        let atoms: HashMap<Element, u64> = parse::parse_formula_v2(formula)?;
-        let atoms = atoms.into_iter().map(|(k, v)| (k, v as usize)).collect();
-        let molecular_weight: f32 = Compound::molecular_weight(&atoms);
+    /// Like `from_formula`, keeping where and why the formula failed to
+    /// parse. An InChI, e.g. InChI=1S/C2H4O2.Na/c1-2(3)4;/h1H3,(H,3,4);/q;+1/p-1,
+    /// is kept in `inchi` and written as its Hill formula, e.g. C2H3NaO2.
+    pub fn new(formula: &str) -> Result<Compound, FormulaError> {
+        let parsed = parse::parse_formula_v2(formula)?;
+        let atoms = parsed.atoms;
+        let adducts = parsed
+            .adducts
+            .iter()
+            .map(|adduct| {
+                Ok(Adduct {
+                    count: adduct.count.clone(),
+                    compound: Compound::new(&adduct.formula)?,
+                })
+            })
+            .collect::<Result<Vec<Adduct>, FormulaError>>()?;
+        let molecular_weight: f64 = Compound::molecular_weight(&atoms);
+        let (formula, inchi) = match formula.starts_with(parse::INCHI_PREFIX) {
+            true => {
+                let adducts: Vec<(Rational, &str)> = parsed
+                    .adducts
+                    .iter()
+                    .map(|adduct| {
+                        (adduct.count.clone(), adduct.formula.as_str())
+                    })
+                    .collect();
+                let hill =
+                    Compound::assemble(&parsed.base, &adducts, parsed.charge);
+                (hill, Some(formula.to_string()))
+            }
+            false => (formula.to_string(), None),
+        };
         Ok(Compound {
-            formula: formula.to_string(),
+            formula,
             atoms,
             molar_mass: molecular_weight,
+            charge: parsed.charge,
+            base: parsed.base,
+            adducts,
+            inchi,
         })
     }
 
-    fn molecular_weight(atoms: &ElementCounts) -> f32 {
-        atoms.iter().fold(0 as f32, |acc, (e, count)| {
-            acc + e.get_atomic_mass() * count.to_owned() as f32
+    /// Writes a formula from its parts, e.g. CuSO4·5H2O
+    fn assemble(
+        base: &str,
+        adducts: &[(Rational, &str)],
+        charge: i32,
+    ) -> String {
+        let adducts: String = adducts
+            .iter()
+            .map(|(count, formula)| match *count == 1 {
+                true => format!("·{}", formula),
+                false => format!("·{}{}", decimal_string(count), formula),
+            })
+            .collect();
+        let charge = match charge.cmp(&0) {
+            Ordering::Equal => String::new(),
+            Ordering::Greater => format!("^{}+", charge),
+            Ordering::Less => format!("^{}-", -charge),
+        };
+        format!("{}{}{}", base, adducts, charge)
+    }
+
+    fn water() -> &'static Compound {
+        static WATER: OnceLock<Compound> = OnceLock::new();
+        WATER.get_or_init(|| Compound::from_formula("H2O").unwrap())
+    }
+
+    fn is_water(&self) -> bool {
+        self.same_species(Compound::water())
+    }
+
+    /// The number of waters of crystallization, e.g. 5 for CuSO4·5H2O or
+    /// 1/2 for CaSO4·0.5H2O
+    pub fn water_count(&self) -> Rational {
+        self.adducts
+            .iter()
+            .filter(|adduct| adduct.compound.is_water())
+            .fold(Rational::new(), |acc, adduct| acc + &adduct.count)
+    }
+
+    /// The mass of water of crystallization in one mole, in grams
+    pub fn water_mass(&self) -> f64 {
+        self.water_count().to_f64() * Compound::water().molar_mass
+    }
+
+    /// The compound with `count` waters of crystallization and its other
+    /// adducts kept, e.g. CuSO4·3H2O from CuSO4·5H2O
+    pub fn with_water(&self, count: impl Into<Rational>) -> Compound {
+        let count: Rational = count.into();
+        let mut adducts: Vec<(Rational, &str)> = self
+            .adducts
+            .iter()
+            .filter(|adduct| !adduct.compound.is_water())
+            .map(|adduct| {
+                (adduct.count.clone(), adduct.compound.formula.as_str())
+            })
+            .collect();
+        if count != 0 {
+            adducts.push((count, "H2O"));
+        }
+        let formula = Compound::assemble(&self.base, &adducts, self.charge);
+        Compound::from_formula(&formula)
+            .expect("Re-assembled formula should parse")
+    }
+
+    /// The compound without its water of crystallization, e.g. CuSO4
+    /// from CuSO4·5H2O
+    pub fn anhydrous(&self) -> Compound {
+        match self.water_count() == 0 {
+            true => self.clone(),
+            false => self.with_water(0),
+        }
+    }
+
+    pub fn anhydrous_mass(&self) -> f64 {
+        self.anhydrous().molar_mass
+    }
+
+    /// Converts a mass of this compound to the mass of another hydrate form
+    /// of it holding as many moles, e.g. CuSO4·5H2O to CuSO4
+    pub fn hydrate_mass(
+        &self,
+        mass: f64,
+        other: &Compound,
+    ) -> Result<f64, String> {
+        match self.anhydrous().same_species(&other.anhydrous()) {
+            true => Ok(mass / self.molar_mass * other.molar_mass),
+            false => Err(format!(
+                "{} is not a hydrate form of {}",
+                other.formula, self.formula
+            )),
+        }
+    }
+
+    /// The compound with a neutral solvent of crystallization joined to it,
+    /// e.g. C8H9NO2·0.5CH2Cl2
+    pub fn with_solvate(
+        &self,
+        count: impl Into<Rational>,
+        solvent: &Compound,
+    ) -> Result<Compound, String> {
+        if solvent.charge != 0 {
+            return Err(format!("Solvent {} must be neutral", solvent.formula));
+        }
+        let count: Rational = count.into();
+        let mut atoms = self.atoms.clone();
+        for (element, n) in &solvent.atoms {
+            *atoms.entry(*element).or_default() += Rational::from(n * &count);
+        }
+        let mut adducts = self.adducts.clone();
+        adducts.push(Adduct {
+            count,
+            compound: solvent.clone(),
+        });
+        let parts: Vec<(Rational, &str)> = adducts
+            .iter()
+            .map(|adduct| {
+                (adduct.count.clone(), adduct.compound.formula.as_str())
+            })
+            .collect();
+        let formula = Compound::assemble(&self.base, &parts, self.charge);
+        Ok(Compound {
+            formula,
+            molar_mass: Compound::molecular_weight(&atoms),
+            atoms,
+            charge: self.charge,
+            base: self.base.clone(),
+            adducts,
+            inchi: None,
         })
     }
 
+    /// The percent of the molar mass from each element, in Hill order
+    pub fn mass_percent(&self) -> Vec<(Element, f64)> {
+        hill_sorted(&self.atoms)
+            .into_iter()
+            .map(|element| {
+                let mass =
+                    element.get_atomic_mass() * self.atoms[element].to_f64();
+                (*element, mass / self.molar_mass * 100.0)
+            })
+            .collect()
+    }
+
+    /// The molecular formula of this empirical formula nearest a molar
+    /// mass, e.g. C6H12O6 for CH2O and 180 g/mol
+    pub fn molecular_formula(
+        &self,
+        molar_mass: f64,
+    ) -> Result<Compound, String> {
+        let multiple = (molar_mass / self.molar_mass).round();
+        if !(multiple >= 1.0 && multiple <= u32::MAX as f64) {
+            return Err(format!(
+                "Molar mass {} is not a multiple of {} ({} g/mol)",
+                molar_mass, self.formula, self.molar_mass
+            ));
+        }
+        let atoms: ElementCounts = self
+            .atoms
+            .iter()
+            .map(|(element, n)| (*element, Rational::from(n * multiple as u32)))
+            .collect();
+        Compound::from_formula(&hill_order(&atoms))
+    }
+
+    /// Sums in element order, so the last digit does not depend on the
+    /// order of the map
+    fn molecular_weight(atoms: &ElementCounts) -> f64 {
+        let mut elements: Vec<&Element> = atoms.keys().collect();
+        elements.sort();
+        elements.into_iter().fold(0 as f64, |acc, e| {
+            acc + e.get_atomic_mass() * atoms[e].to_f64()
+        })
+    }
+
+    /// Whether both compounds have the same atoms and charge, however
+    /// their formulas are written
+    pub fn same_species(&self, other: &Compound) -> bool {
+        self == other
+    }
+
+    /// The formula in Hill notation with any charge, e.g. C7H6O2 for
+    /// C6H5COOH or O4S^2- for SO4^2-
+    pub fn hill_formula(&self) -> String {
+        Compound::assemble(&hill_order(&self.atoms), &[], self.charge)
+    }
+
+    /// The mass of the molecule or ion made of the most abundant isotope
+    /// of each element, in daltons
+    pub fn monoisotopic_mass(&self) -> Result<f64, String> {
+        mass_spec::monoisotopic_mass(&self.atoms, self.charge)
+    }
+
+    /// The isotope pattern from natural abundances: each peak's m/z and
+    /// intensity relative to the largest peak
+    pub fn isotope_pattern(&self) -> Result<Vec<Peak>, String> {
+        mass_spec::isotope_pattern(&self.atoms, self.charge)
+    }
+
+    /// The atoms in element order and the charge, which identify the
+    /// compound
+    fn identity(&self) -> (Vec<(&Element, &Rational)>, i32) {
+        let mut atoms: Vec<(&Element, &Rational)> = self.atoms.iter().collect();
+        atoms.sort();
+        (atoms, self.charge)
+    }
+
+    pub fn is_charged(&self) -> bool {
+        self.charge != 0
+    }
+
     pub fn all_elements(&self) -> Vec<&Element> {
-        return self.atoms.keys().collect();
+        self.atoms.keys().collect()
+    }
+}
+
+impl PartialEq for Compound {
+    fn eq(&self, other: &Self) -> bool {
+        self.atoms == other.atoms && self.charge == other.charge
+    }
+}
+
+impl Eq for Compound {}
+
+impl Hash for Compound {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state);
+    }
+}
+
+impl PartialOrd for Compound {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Compound {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.identity().cmp(&other.identity())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use math::round::half_up;
+    use rug::Rational;
 
     use crate::model::compound::Compound;
 
-    fn round(weight: f32) -> f64 {
-        half_up(weight as f64, 2)
+    fn round(weight: f64) -> f64 {
+        half_up(weight, 2)
     }
 
     #[test]
@@ -56,6 +374,14 @@ mod tests {
         assert_eq!(weight, 30.07);
     }
 
+    #[test]
+    fn molar_mass_is_deterministic() {
+        let masses: Vec<f64> = (0..20)
+            .map(|_| Compound::from_formula("C8H9NO2").unwrap().molar_mass)
+            .collect();
+        assert!(masses.iter().all(|m| *m == masses[0]));
+    }
+
     #[test]
     fn cellulose() {
         let compound = Compound::from_formula("C6H10O5").unwrap();
@@ -63,10 +389,185 @@ mod tests {
         assert_eq!(round(weight), 162.14);
     }
 
+    #[test]
+    fn permanganate() {
+        let compound = Compound::from_formula("MnO4-").unwrap();
+        assert_eq!(compound.charge, -1);
+        assert_eq!(round(compound.molar_mass), 118.93);
+    }
+
     #[test]
     fn vanadium_acetylacetonate() {
-        let compound = Compound::from_formula("V1C15H21O6").unwrap();
+        let compound = Compound::from_formula("V(acac)3").unwrap();
         let weight = compound.molar_mass;
         assert_eq!(round(weight), 348.27);
     }
+
+    #[test]
+    fn copper_sulfate_pentahydrate() {
+        let compound = Compound::from_formula("CuSO4·5H2O").unwrap();
+        assert_eq!(compound.base, "CuSO4");
+        assert_eq!(compound.water_count(), 5);
+        assert_eq!(round(compound.molar_mass), 249.69);
+        assert_eq!(round(compound.anhydrous_mass()), 159.62);
+        assert_eq!(round(compound.water_mass()), 90.08);
+        assert_eq!(compound.anhydrous().formula, "CuSO4");
+        assert_eq!(compound.with_water(3).formula, "CuSO4·3H2O");
+    }
+
+    #[test]
+    fn mixed_adducts() {
+        let compound = Compound::from_formula("AlCl3·2NH3*3H2O").unwrap();
+        assert_eq!(compound.adducts.len(), 2);
+        assert_eq!(compound.adducts[0].count, 2);
+        assert_eq!(compound.adducts[0].compound.formula, "NH3");
+        assert_eq!(compound.water_count(), 3);
+        assert_eq!(compound.anhydrous().formula, "AlCl3·2NH3");
+    }
+
+    #[test]
+    fn hydrate_mass() {
+        let hydrate = Compound::from_formula("CuSO4*5H2O").unwrap();
+        let anhydrous = Compound::from_formula("CuSO4").unwrap();
+        let mass = hydrate.hydrate_mass(10.0, &anhydrous).unwrap();
+        assert_eq!(round(mass), 6.39);
+        let other = Compound::from_formula("MgSO4·7H2O").unwrap();
+        assert!(hydrate.hydrate_mass(1.0, &other).is_err());
+    }
+
+    #[test]
+    fn mass_percent() {
+        let glucose = Compound::from_formula("C6H12O6").unwrap();
+        let percents: Vec<(String, f64)> = glucose
+            .mass_percent()
+            .iter()
+            .map(|(element, percent)| (element.to_string(), round(*percent)))
+            .collect();
+        assert_eq!(
+            percents,
+            vec![
+                ("C".to_string(), 40.0),
+                ("H".to_string(), 6.71),
+                ("O".to_string(), 53.28)
+            ]
+        );
+        let total: f64 = glucose.mass_percent().iter().map(|(_, p)| p).sum();
+        assert!((total - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn with_solvate() {
+        let paracetamol = Compound::from_formula("C8H9NO2").unwrap();
+        let dichloromethane = Compound::from_formula("CH2Cl2").unwrap();
+        let solvate = paracetamol
+            .with_solvate(Rational::from((1, 2)), &dichloromethane)
+            .unwrap();
+        assert_eq!(solvate.formula, "C8H9NO2·0.5CH2Cl2");
+        assert_eq!(
+            solvate,
+            Compound::from_formula("C8H9NO2·0.5CH2Cl2").unwrap()
+        );
+        assert_eq!(
+            round(solvate.molar_mass),
+            round(
+                Compound::from_formula("C8H9NO2·0.5CH2Cl2")
+                    .unwrap()
+                    .molar_mass
+            )
+        );
+        let water = Compound::from_formula("H2O").unwrap();
+        let sulfate = Compound::from_formula("SO4^2-").unwrap();
+        let hydrated = sulfate.with_solvate(2, &water).unwrap();
+        assert_eq!(hydrated.formula, "SO4·2H2O^2-");
+        assert_eq!(hydrated, Compound::from_formula("SO4·2H2O^2-").unwrap());
+        assert_eq!(hydrated.water_count(), 2);
+        let ammonium = Compound::from_formula("NH4+").unwrap();
+        assert!(paracetamol.with_solvate(1, &ammonium).is_err());
+    }
+
+    #[test]
+    fn molecular_formula() {
+        let empirical = Compound::from_formula("CH2O").unwrap();
+        let glucose = empirical.molecular_formula(180.0).unwrap();
+        assert_eq!(glucose.formula, "C6H12O6");
+        assert_eq!(empirical.molecular_formula(31.0).unwrap().formula, "CH2O");
+        assert!(empirical.molecular_formula(10.0).is_err());
+    }
+
+    #[test]
+    fn labelled() {
+        let heavy_water = Compound::from_formula("D2O").unwrap();
+        assert_eq!(round(heavy_water.molar_mass), 20.03);
+        let labelled = Compound::from_formula("H2[18O]").unwrap();
+        assert_eq!(round(labelled.molar_mass), 20.02);
+        let methyl_iodide = Compound::from_formula("¹³CH3I").unwrap();
+        assert_eq!(round(methyl_iodide.molar_mass), 142.93);
+    }
+
+    #[test]
+    fn non_stoichiometric() {
+        let wustite = Compound::from_formula("Fe0.95O").unwrap();
+        assert_eq!(round(wustite.molar_mass), 69.05);
+        let nmc = Compound::from_formula("LiNi0.8Co0.1Mn0.1O2").unwrap();
+        assert_eq!(round(nmc.molar_mass), 97.34);
+        let hemihydrate = Compound::from_formula("CaSO4·0.5H2O").unwrap();
+        assert_eq!(hemihydrate.water_count(), Rational::from((1, 2)));
+        assert_eq!(hemihydrate.with_water(2).formula, "CaSO4·2H2O");
+    }
+
+    #[test]
+    fn hill_formula() {
+        let benzoic_acid = Compound::from_formula("C6H5COOH").unwrap();
+        assert_eq!(benzoic_acid.hill_formula(), "C7H6O2");
+        let sulfuric_acid = Compound::from_formula("H2SO4").unwrap();
+        assert_eq!(sulfuric_acid.hill_formula(), "H2O4S");
+        let sulfate = Compound::from_formula("SO4^2-").unwrap();
+        assert_eq!(sulfate.hill_formula(), "O4S^2-");
+        let labelled = Compound::from_formula("CD3OD").unwrap();
+        assert_eq!(labelled.hill_formula(), "CD4O");
+        let hydrate = Compound::from_formula("CuSO4·5H2O").unwrap();
+        assert_eq!(hydrate.hill_formula(), "CuH10O9S");
+    }
+
+    #[test]
+    fn identity() {
+        let written = ["C6H5COOH", "C7H6O2", "PhCO2H", "smiles:OC(=O)c1ccccc1"];
+        let compounds: Vec<Compound> = written
+            .iter()
+            .map(|f| Compound::from_formula(f).unwrap())
+            .collect();
+        assert!(compounds.windows(2).all(|pair| pair[0] == pair[1]));
+        let unique: HashSet<Compound> = compounds.into_iter().collect();
+        assert_eq!(unique.len(), 1);
+        let ammonia = Compound::from_formula("NH3").unwrap();
+        let ammonium = Compound::from_formula("NH4+").unwrap();
+        assert_ne!(ammonia, Compound::from_formula("NH4").unwrap());
+        assert_ne!(ammonium, Compound::from_formula("NH4").unwrap());
+        let mut sorted: Vec<Compound> = ["H2O", "CH4", "NH3", "H2"]
+            .iter()
+            .map(|f| Compound::from_formula(f).unwrap())
+            .collect();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 4);
+        assert!(sorted.binary_search(&ammonia).is_ok());
+    }
+
+    #[test]
+    fn inchi() {
+        let inchi = "InChI=1S/C2H4O2.Na/c1-2(3)4;/h1H3,(H,3,4);/q;+1/p-1";
+        let acetate = Compound::from_formula(inchi).unwrap();
+        assert_eq!(acetate.base, "C2H3NaO2");
+        assert_eq!(acetate.formula, "C2H3NaO2");
+        assert_eq!(acetate.inchi.as_deref(), Some(inchi));
+        assert_eq!(round(acetate.molar_mass), 82.03);
+        let hydrate = Compound::from_formula(
+            "InChI=1S/Cu.H2O4S.5H2O/c;1-5(2,3)4;;;;;/h;(H2,1,2,3,4);5*1H2/q+2;/p-2",
+        )
+        .unwrap();
+        assert_eq!(hydrate.water_count(), 5);
+        assert_eq!(hydrate.formula, "CuO4S·5H2O");
+        assert_eq!(hydrate.anhydrous().formula, "CuO4S");
+        assert!(Compound::from_formula("InChI=1S/Xx").is_err());
+    }
 }
@@ -1,13 +1,27 @@
+use std::fmt::{Display, Formatter};
+
 use periodic_table_on_an_enum::Element as PElement;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+use crate::model::atomic_weights::atomic_weight;
+use crate::model::isotope::{find_isotope, isotopes_of, Isotope};
+
+/// An element, or one isotope of it when a mass number is given, e.g. C or
+/// 13C. Isotopes of an element are different atoms, so balancing conserves
+/// each label.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Element {
     element: PElement,
+    mass_number: Option<u16>,
 }
 
 impl Element {
+    /// Looks up a symbol, where D and T are deuterium and tritium
     pub fn from_symbol(sym: &str) -> Option<Element> {
-        PElement::from_symbol(sym).map(Element::from_pt_element)
+        match sym {
+            "D" => Element::from_symbol("H")?.with_mass_number(2),
+            "T" => Element::from_symbol("H")?.with_mass_number(3),
+            _ => PElement::from_symbol(sym).map(Element::from_pt_element),
+        }
     }
 
     pub fn from_atomic_number(z: usize) -> Option<Element> {
@@ -15,10 +29,68 @@ impl Element {
     }
 
     fn from_pt_element(element: PElement) -> Element {
-        Element { element }
+        Element {
+            element,
+            mass_number: None,
+        }
     }
 
-    pub fn get_atomic_mass(&self) -> f32 {
-        self.element.get_atomic_mass()
+    /// The isotope of this element with the given mass number, if it is in
+    /// the isotope table
+    pub fn with_mass_number(&self, mass_number: u16) -> Option<Element> {
+        find_isotope(self.get_symbol(), mass_number).map(|_| Element {
+            element: self.element,
+            mass_number: Some(mass_number),
+        })
+    }
+
+    pub fn mass_number(&self) -> Option<u16> {
+        self.mass_number
+    }
+
+    /// The element without an isotope label
+    pub fn natural(&self) -> Element {
+        Element::from_pt_element(self.element)
+    }
+
+    /// The labelled isotope, if any
+    pub fn isotope(&self) -> Option<Isotope> {
+        self.mass_number.and_then(|mass_number| {
+            find_isotope(self.get_symbol(), mass_number)
+        })
+    }
+
+    /// The isotopes of the element, whether or not this one is labelled
+    pub fn isotopes(&self) -> Vec<Isotope> {
+        isotopes_of(self.get_symbol())
+    }
+
+    /// The atomic weight from the chosen table, or the mass of the
+    /// labelled isotope
+    pub fn get_atomic_mass(&self) -> f64 {
+        match self.isotope() {
+            Some(isotope) => isotope.mass,
+            None => {
+                atomic_weight(self.get_symbol(), self.element.get_atomic_mass())
+            }
+        }
+    }
+
+    pub fn get_symbol(&self) -> &'static str {
+        self.element.get_symbol()
+    }
+}
+
+impl Display for Element {
+    /// Writes the symbol, D, T, or a label like [13C]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.get_symbol(), self.mass_number) {
+            (symbol, None) => write!(f, "{}", symbol),
+            ("H", Some(2)) => write!(f, "D"),
+            ("H", Some(3)) => write!(f, "T"),
+            (symbol, Some(mass_number)) => {
+                write!(f, "[{}{}]", mass_number, symbol)
+            }
+        }
     }
 }
@@ -0,0 +1,272 @@
+use std::collections::HashSet;
+
+use rug::{Integer, Rational};
+
+use crate::model::{hill_order, Compound, Element, ElementCounts};
+use crate::solve::{limit_denominator, scale_to_integers};
+
+/// The usual allowance between found and calculated percentages, in
+/// percentage points
+pub const ANALYSIS_TOLERANCE: f64 = 0.4;
+
+/// Solvents often left in a sample sent for analysis
+const COMMON_SOLVENTS: &[&str] = &[
+    "H2O", "CH2Cl2", "CHCl3", "CH3OH", "C2H5OH", "C3H6O", "C2H3N", "C4H8O2",
+    "C4H10O", "C4H8O", "C6H14", "C7H8", "C3H7NO", "C2H6OS",
+];
+
+/// The largest denominator tried for mole ratios, so that ratios like 1.5
+/// or 1.33 are read as 3/2 and 4/3 rather than rounded
+pub const MAX_RATIO_DENOMINATOR: u64 = 6;
+
+/// One element of an analysis, in percent by mass
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisRow {
+    pub element: Element,
+    pub calculated: f64,
+    pub found: f64,
+}
+
+impl AnalysisRow {
+    /// Found minus calculated, in percentage points
+    pub fn deviation(&self) -> f64 {
+        self.found - self.calculated
+    }
+}
+
+/// Found percentages of elements, e.g. from CHN analysis, compared with
+/// those calculated for a formula
+#[derive(Debug, Clone)]
+pub struct ElementalAnalysis {
+    pub compound: Compound,
+    pub rows: Vec<AnalysisRow>,
+}
+
+impl ElementalAnalysis {
+    pub fn new(compound: &Compound, found: &[(Element, f64)]) -> Self {
+        let calculated = compound.mass_percent();
+        let rows = found
+            .iter()
+            .map(|(element, found)| AnalysisRow {
+                element: *element,
+                calculated: calculated
+                    .iter()
+                    .find(|(e, _)| e == element)
+                    .map_or(0.0, |(_, percent)| *percent),
+                found: *found,
+            })
+            .collect();
+        ElementalAnalysis {
+            compound: compound.clone(),
+            rows,
+        }
+    }
+
+    /// The largest deviation of any element, in percentage points
+    pub fn max_deviation(&self) -> f64 {
+        self.rows
+            .iter()
+            .map(|row| row.deviation().abs())
+            .fold(0.0, f64::max)
+    }
+
+    /// Whether every element is within the tolerance, usually ±0.4
+    pub fn passes(&self, tolerance: f64) -> bool {
+        self.max_deviation() <= tolerance
+    }
+
+    /// The compounds of `COMMON_SOLVENTS`, water and CH2Cl2 first
+    pub fn common_solvents() -> Vec<Compound> {
+        COMMON_SOLVENTS
+            .iter()
+            .map(|formula| Compound::from_formula(formula).unwrap())
+            .collect()
+    }
+
+    /// Analyses of the compound with no solvent and with each amount of
+    /// each solvent, in steps of `step` up to `max`, e.g. ·0.25CH2Cl2,
+    /// best fit first
+    pub fn fit_solvates(
+        compound: &Compound,
+        found: &[(Element, f64)],
+        solvents: &[Compound],
+        step: &Rational,
+        max: &Rational,
+    ) -> Result<Vec<ElementalAnalysis>, String> {
+        if step.cmp0().is_le() {
+            return Err(format!("Solvate step must be positive, not {}", step));
+        }
+        let mut analyses = vec![ElementalAnalysis::new(compound, found)];
+        for solvent in solvents {
+            let mut count = step.clone();
+            while &count <= max {
+                let solvate = compound.with_solvate(count.clone(), solvent)?;
+                analyses.push(ElementalAnalysis::new(&solvate, found));
+                count += step;
+            }
+        }
+        let squares = |analysis: &ElementalAnalysis| -> f64 {
+            analysis
+                .rows
+                .iter()
+                .map(|row| row.deviation().powi(2))
+                .sum()
+        };
+        analyses.sort_by(|a, b| {
+            a.max_deviation()
+                .total_cmp(&b.max_deviation())
+                .then(squares(a).total_cmp(&squares(b)))
+        });
+        Ok(analyses)
+    }
+
+    /// Empirical formulas for percentages by mass, e.g. CH2O for C 40.0,
+    /// H 6.71 and O 53.3, found by limiting the denominators of the mole
+    /// ratios. Those within `tolerance` of every percentage are returned,
+    /// best fit first.
+    pub fn empirical_formulas(
+        percents: &[(Element, f64)],
+        tolerance: f64,
+    ) -> Result<Vec<ElementalAnalysis>, String> {
+        if percents.is_empty() {
+            return Err("No percentages given".to_string());
+        }
+        let mut seen = HashSet::new();
+        for (element, percent) in percents {
+            if !seen.insert(element) {
+                return Err(format!("{} is given more than once", element));
+            }
+            if !(percent.is_finite() && *percent > 0.0) {
+                return Err(format!(
+                    "Invalid percentage {} of {}",
+                    percent, element
+                ));
+            }
+        }
+        let moles: Vec<f64> = percents
+            .iter()
+            .map(|(element, percent)| percent / element.get_atomic_mass())
+            .collect();
+        let least = moles.iter().copied().fold(f64::INFINITY, f64::min);
+        let mut candidates: Vec<Vec<Integer>> = vec![];
+        for max_denominator in 1..=MAX_RATIO_DENOMINATOR {
+            let ratios = moles
+                .iter()
+                .map(|n| {
+                    let ratio = Rational::from_f64(n / least)
+                        .ok_or_else(|| format!("Invalid mole ratio {}", n))?;
+                    limit_denominator(ratio, max_denominator)
+                })
+                .collect::<Result<Vec<Rational>, String>>()?;
+            let counts = scale_to_integers(&ratios);
+            if counts.iter().all(|c| c.cmp0().is_gt())
+                && !candidates.contains(&counts)
+            {
+                candidates.push(counts);
+            }
+        }
+        let mut analyses = candidates
+            .into_iter()
+            .map(|counts| {
+                let atoms: ElementCounts = percents
+                    .iter()
+                    .zip(counts)
+                    .map(|((element, _), count)| {
+                        (*element, Rational::from(count))
+                    })
+                    .collect();
+                let compound = Compound::from_formula(&hill_order(&atoms))?;
+                Ok(ElementalAnalysis::new(&compound, percents))
+            })
+            .collect::<Result<Vec<ElementalAnalysis>, String>>()?;
+        analyses.retain(|analysis| analysis.passes(tolerance));
+        analyses
+            .sort_by(|a, b| a.max_deviation().total_cmp(&b.max_deviation()));
+        match analyses.is_empty() {
+            true => Err(format!(
+                "No formula with mole ratio denominators up to {} is within \
+                 {} of the percentages",
+                MAX_RATIO_DENOMINATOR, tolerance
+            )),
+            false => Ok(analyses),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::Rational;
+
+    use crate::model::{Compound, Element, ElementalAnalysis};
+
+    fn found(percents: &[(&str, f64)]) -> Vec<(Element, f64)> {
+        percents
+            .iter()
+            .map(|(symbol, percent)| {
+                (Element::from_symbol(symbol).unwrap(), *percent)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_analysis() {
+        let paracetamol = Compound::from_formula("C8H9NO2").unwrap();
+        let analysis = ElementalAnalysis::new(
+            &paracetamol,
+            &found(&[("C", 63.40), ("H", 6.10), ("N", 9.30)]),
+        );
+        let deviations: Vec<f64> = analysis
+            .rows
+            .iter()
+            .map(|row| math::round::half_up(row.deviation(), 2))
+            .collect();
+        assert_eq!(deviations, vec![-0.16, 0.1, 0.03]);
+        assert!(analysis.passes(0.4));
+        let missing =
+            ElementalAnalysis::new(&paracetamol, &found(&[("S", 1.0)]));
+        assert!(!missing.passes(0.4));
+    }
+
+    #[test]
+    fn test_empirical_formulas() {
+        let formulas = |percents, tolerance| {
+            ElementalAnalysis::empirical_formulas(&found(percents), tolerance)
+                .unwrap()
+                .iter()
+                .map(|analysis| analysis.compound.formula.clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            formulas(&[("C", 40.0), ("H", 6.71), ("O", 53.3)], 0.4),
+            vec!["CH2O"]
+        );
+        // C 1, H 1.33 is C3H4
+        assert_eq!(formulas(&[("C", 89.94), ("H", 10.06)], 0.1), vec!["C3H4"]);
+        // Fe2O3, with Fe 1, O 1.5
+        assert_eq!(formulas(&[("Fe", 69.94), ("O", 30.06)], 0.4)[0], "Fe2O3");
+        let error = |percents: &[(&str, f64)]| {
+            ElementalAnalysis::empirical_formulas(&found(percents), 0.4)
+                .unwrap_err()
+        };
+        error(&[]);
+        error(&[("C", 50.0), ("C", 50.0)]);
+        error(&[("C", 100.0), ("H", 0.0)]);
+    }
+
+    #[test]
+    fn test_solvate_fit() {
+        // calculated for C8H9NO2·0.5CH2Cl2: C 52.72, H 5.21, N 7.23
+        let paracetamol = Compound::from_formula("C8H9NO2").unwrap();
+        let analyses = ElementalAnalysis::fit_solvates(
+            &paracetamol,
+            &found(&[("C", 52.65), ("H", 5.25), ("N", 7.20)]),
+            &ElementalAnalysis::common_solvents(),
+            &Rational::from((1, 4)),
+            &Rational::from(2),
+        )
+        .unwrap();
+        assert_eq!(analyses[0].compound.formula, "C8H9NO2·0.5CH2Cl2");
+        assert!(analyses[0].passes(0.4));
+        assert!(!analyses.last().unwrap().passes(0.4));
+    }
+}
@@ -0,0 +1,376 @@
+/// A nuclide of an element, with its mass in daltons and its natural
+/// abundance as a fraction, 0 for radioactive labels such as 14C
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Isotope {
+    pub symbol: &'static str,
+    pub mass_number: u16,
+    pub mass: f64,
+    pub abundance: f64,
+}
+
+/// The stable isotopes of the elements, from H to U, and common radioactive
+/// labels: symbol, mass number, mass (Da) and natural abundance
+#[rustfmt::skip]
+const ISOTOPES: &[(&str, u16, f64, f64)] = &[
+    ("H", 1, 1.00782503207, 0.999885),
+    ("H", 2, 2.0141017778, 0.000115),
+    ("H", 3, 3.0160492777, 0.0),
+    ("He", 3, 3.0160293191, 0.00000134),
+    ("He", 4, 4.00260325415, 0.99999866),
+    ("Li", 6, 6.015122795, 0.0759),
+    ("Li", 7, 7.01600455, 0.9241),
+    ("Be", 9, 9.0121822, 1.0),
+    ("B", 10, 10.0129370, 0.199),
+    ("B", 11, 11.0093054, 0.801),
+    ("C", 11, 11.0114336, 0.0),
+    ("C", 12, 12.0, 0.9893),
+    ("C", 13, 13.0033548378, 0.0107),
+    ("C", 14, 14.003241989, 0.0),
+    ("N", 14, 14.0030740048, 0.99636),
+    ("N", 15, 15.0001088982, 0.00364),
+    ("O", 15, 15.0030656, 0.0),
+    ("O", 16, 15.99491461956, 0.99757),
+    ("O", 17, 16.99913170, 0.00038),
+    ("O", 18, 17.9991610, 0.00205),
+    ("F", 18, 18.0009380, 0.0),
+    ("F", 19, 18.99840322, 1.0),
+    ("Ne", 20, 19.9924401754, 0.9048),
+    ("Ne", 21, 20.99384668, 0.0027),
+    ("Ne", 22, 21.991385114, 0.0925),
+    ("Na", 23, 22.9897692809, 1.0),
+    ("Mg", 24, 23.985041700, 0.7899),
+    ("Mg", 25, 24.98583692, 0.1000),
+    ("Mg", 26, 25.982592929, 0.1101),
+    ("Al", 27, 26.98153863, 1.0),
+    ("Si", 28, 27.9769265325, 0.92223),
+    ("Si", 29, 28.976494700, 0.04685),
+    ("Si", 30, 29.97377017, 0.03092),
+    ("P", 31, 30.97376163, 1.0),
+    ("P", 32, 31.97390727, 0.0),
+    ("S", 32, 31.97207100, 0.9499),
+    ("S", 33, 32.97145876, 0.0075),
+    ("S", 34, 33.96786690, 0.0425),
+    ("S", 35, 34.96903231, 0.0),
+    ("S", 36, 35.96708076, 0.0001),
+    ("Cl", 35, 34.96885268, 0.7576),
+    ("Cl", 37, 36.96590259, 0.2424),
+    ("Ar", 36, 35.967545106, 0.003336),
+    ("Ar", 38, 37.9627324, 0.000629),
+    ("Ar", 40, 39.9623831225, 0.996035),
+    ("K", 39, 38.96370668, 0.932581),
+    ("K", 40, 39.96399848, 0.000117),
+    ("K", 41, 40.96182576, 0.067302),
+    ("Ca", 40, 39.96259098, 0.96941),
+    ("Ca", 42, 41.95861801, 0.00647),
+    ("Ca", 43, 42.9587666, 0.00135),
+    ("Ca", 44, 43.9554818, 0.02086),
+    ("Ca", 46, 45.9536926, 0.00004),
+    ("Ca", 48, 47.952534, 0.00187),
+    ("Sc", 45, 44.9559119, 1.0),
+    ("Ti", 46, 45.9526316, 0.0825),
+    ("Ti", 47, 46.9517631, 0.0744),
+    ("Ti", 48, 47.9479463, 0.7372),
+    ("Ti", 49, 48.9478700, 0.0541),
+    ("Ti", 50, 49.9447912, 0.0518),
+    ("V", 50, 49.9471585, 0.00250),
+    ("V", 51, 50.9439595, 0.99750),
+    ("Cr", 50, 49.9460442, 0.04345),
+    ("Cr", 52, 51.9405075, 0.83789),
+    ("Cr", 53, 52.9406494, 0.09501),
+    ("Cr", 54, 53.9388804, 0.02365),
+    ("Mn", 55, 54.9380451, 1.0),
+    ("Fe", 54, 53.9396105, 0.05845),
+    ("Fe", 56, 55.9349375, 0.91754),
+    ("Fe", 57, 56.9353940, 0.02119),
+    ("Fe", 58, 57.9332756, 0.00282),
+    ("Co", 59, 58.9331950, 1.0),
+    ("Co", 60, 59.9338171, 0.0),
+    ("Ni", 58, 57.9353429, 0.680769),
+    ("Ni", 60, 59.9307864, 0.262231),
+    ("Ni", 61, 60.9310560, 0.011399),
+    ("Ni", 62, 61.9283451, 0.036345),
+    ("Ni", 64, 63.9279660, 0.009256),
+    ("Cu", 63, 62.9295975, 0.6915),
+    ("Cu", 65, 64.9277895, 0.3085),
+    ("Zn", 64, 63.9291422, 0.48268),
+    ("Zn", 66, 65.9260334, 0.27975),
+    ("Zn", 67, 66.9271273, 0.04102),
+    ("Zn", 68, 67.9248442, 0.19024),
+    ("Zn", 70, 69.9253193, 0.00631),
+    ("Ga", 69, 68.9255736, 0.60108),
+    ("Ga", 71, 70.9247013, 0.39892),
+    ("Ge", 70, 69.9242474, 0.2038),
+    ("Ge", 72, 71.9220758, 0.2731),
+    ("Ge", 73, 72.9234589, 0.0776),
+    ("Ge", 74, 73.9211778, 0.3672),
+    ("Ge", 76, 75.9214026, 0.0783),
+    ("As", 75, 74.9215965, 1.0),
+    ("Se", 74, 73.9224764, 0.0089),
+    ("Se", 76, 75.9192136, 0.0937),
+    ("Se", 77, 76.9199140, 0.0763),
+    ("Se", 78, 77.9173091, 0.2377),
+    ("Se", 80, 79.9165213, 0.4961),
+    ("Se", 82, 81.9166994, 0.0873),
+    ("Br", 79, 78.9183371, 0.5069),
+    ("Br", 81, 80.9162906, 0.4931),
+    ("Kr", 78, 77.9203648, 0.00355),
+    ("Kr", 80, 79.9163790, 0.02286),
+    ("Kr", 82, 81.9134836, 0.11593),
+    ("Kr", 83, 82.914136, 0.11500),
+    ("Kr", 84, 83.911507, 0.56987),
+    ("Kr", 86, 85.91061073, 0.17279),
+    ("Rb", 85, 84.911789738, 0.7217),
+    ("Rb", 87, 86.909180527, 0.2783),
+    ("Sr", 84, 83.913425, 0.0056),
+    ("Sr", 86, 85.9092602, 0.0986),
+    ("Sr", 87, 86.9088771, 0.0700),
+    ("Sr", 88, 87.9056121, 0.8258),
+    ("Sr", 90, 89.9077279, 0.0),
+    ("Y", 89, 88.9058483, 1.0),
+    ("Zr", 90, 89.9047044, 0.5145),
+    ("Zr", 91, 90.9056458, 0.1122),
+    ("Zr", 92, 91.9050408, 0.1715),
+    ("Zr", 94, 93.9063152, 0.1738),
+    ("Zr", 96, 95.9082734, 0.0280),
+    ("Nb", 93, 92.9063781, 1.0),
+    ("Mo", 92, 91.906811, 0.1477),
+    ("Mo", 94, 93.9050883, 0.0923),
+    ("Mo", 95, 94.9058421, 0.1590),
+    ("Mo", 96, 95.9046795, 0.1668),
+    ("Mo", 97, 96.9060215, 0.0956),
+    ("Mo", 98, 97.9054082, 0.2419),
+    ("Mo", 100, 99.907477, 0.0967),
+    ("Tc", 99, 98.9062547, 0.0),
+    ("Ru", 96, 95.907598, 0.0554),
+    ("Ru", 98, 97.905287, 0.0187),
+    ("Ru", 99, 98.9059393, 0.1276),
+    ("Ru", 100, 99.9042195, 0.1260),
+    ("Ru", 101, 100.9055821, 0.1706),
+    ("Ru", 102, 101.9043493, 0.3155),
+    ("Ru", 104, 103.905433, 0.1862),
+    ("Rh", 103, 102.905504, 1.0),
+    ("Pd", 102, 101.905609, 0.0102),
+    ("Pd", 104, 103.904036, 0.1114),
+    ("Pd", 105, 104.905085, 0.2233),
+    ("Pd", 106, 105.903486, 0.2733),
+    ("Pd", 108, 107.903892, 0.2646),
+    ("Pd", 110, 109.905153, 0.1172),
+    ("Ag", 107, 106.905097, 0.51839),
+    ("Ag", 109, 108.904752, 0.48161),
+    ("Cd", 106, 105.906459, 0.0125),
+    ("Cd", 108, 107.904184, 0.0089),
+    ("Cd", 110, 109.9030021, 0.1249),
+    ("Cd", 111, 110.9041781, 0.1280),
+    ("Cd", 112, 111.9027578, 0.2413),
+    ("Cd", 113, 112.9044017, 0.1222),
+    ("Cd", 114, 113.9033585, 0.2873),
+    ("Cd", 116, 115.904756, 0.0749),
+    ("In", 113, 112.904058, 0.0429),
+    ("In", 115, 114.903878, 0.9571),
+    ("Sn", 112, 111.904818, 0.0097),
+    ("Sn", 114, 113.902779, 0.0066),
+    ("Sn", 115, 114.903342, 0.0034),
+    ("Sn", 116, 115.901741, 0.1454),
+    ("Sn", 117, 116.902952, 0.0768),
+    ("Sn", 118, 117.901603, 0.2422),
+    ("Sn", 119, 118.903308, 0.0859),
+    ("Sn", 120, 119.9021947, 0.3258),
+    ("Sn", 122, 121.9034390, 0.0463),
+    ("Sn", 124, 123.9052739, 0.0579),
+    ("Sb", 121, 120.9038157, 0.5721),
+    ("Sb", 123, 122.9042140, 0.4279),
+    ("Te", 120, 119.904020, 0.0009),
+    ("Te", 122, 121.9030439, 0.0255),
+    ("Te", 123, 122.9042700, 0.0089),
+    ("Te", 124, 123.9028179, 0.0474),
+    ("Te", 125, 124.9044307, 0.0707),
+    ("Te", 126, 125.9033117, 0.1884),
+    ("Te", 128, 127.9044631, 0.3174),
+    ("Te", 130, 129.9062244, 0.3408),
+    ("I", 125, 124.9046302, 0.0),
+    ("I", 127, 126.904473, 1.0),
+    ("I", 131, 130.9061246, 0.0),
+    ("Xe", 124, 123.9058930, 0.000952),
+    ("Xe", 126, 125.904274, 0.000890),
+    ("Xe", 128, 127.9035313, 0.019102),
+    ("Xe", 129, 128.9047794, 0.264006),
+    ("Xe", 130, 129.9035080, 0.040710),
+    ("Xe", 131, 130.9050824, 0.212324),
+    ("Xe", 132, 131.9041535, 0.269086),
+    ("Xe", 134, 133.9053945, 0.104357),
+    ("Xe", 136, 135.907219, 0.088573),
+    ("Cs", 133, 132.905451933, 1.0),
+    ("Cs", 137, 136.9070895, 0.0),
+    ("Ba", 130, 129.9063208, 0.00106),
+    ("Ba", 132, 131.9050613, 0.00101),
+    ("Ba", 134, 133.9045084, 0.02417),
+    ("Ba", 135, 134.9056886, 0.06592),
+    ("Ba", 136, 135.9045759, 0.07854),
+    ("Ba", 137, 136.9058274, 0.11232),
+    ("Ba", 138, 137.9052472, 0.71698),
+    ("La", 138, 137.907112, 0.00090),
+    ("La", 139, 138.9063533, 0.99910),
+    ("Ce", 136, 135.907172, 0.00185),
+    ("Ce", 138, 137.905991, 0.00251),
+    ("Ce", 140, 139.9054387, 0.88450),
+    ("Ce", 142, 141.909244, 0.11114),
+    ("Pr", 141, 140.9076528, 1.0),
+    ("Nd", 142, 141.9077233, 0.272),
+    ("Nd", 143, 142.9098143, 0.122),
+    ("Nd", 144, 143.9100873, 0.238),
+    ("Nd", 145, 144.9125736, 0.083),
+    ("Nd", 146, 145.9131169, 0.172),
+    ("Nd", 148, 147.916893, 0.057),
+    ("Nd", 150, 149.920891, 0.056),
+    ("Sm", 144, 143.911999, 0.0307),
+    ("Sm", 147, 146.9148979, 0.1499),
+    ("Sm", 148, 147.9148227, 0.1124),
+    ("Sm", 149, 148.9171847, 0.1382),
+    ("Sm", 150, 149.9172755, 0.0738),
+    ("Sm", 152, 151.9197324, 0.2675),
+    ("Sm", 154, 153.9222093, 0.2275),
+    ("Eu", 151, 150.9198502, 0.4781),
+    ("Eu", 153, 152.9212303, 0.5219),
+    ("Gd", 152, 151.9197910, 0.0020),
+    ("Gd", 154, 153.9208656, 0.0218),
+    ("Gd", 155, 154.9226220, 0.1480),
+    ("Gd", 156, 155.9221227, 0.2047),
+    ("Gd", 157, 156.9239601, 0.1565),
+    ("Gd", 158, 157.9241039, 0.2484),
+    ("Gd", 160, 159.9270541, 0.2186),
+    ("Tb", 159, 158.9253468, 1.0),
+    ("Dy", 156, 155.924283, 0.00056),
+    ("Dy", 158, 157.924409, 0.00095),
+    ("Dy", 160, 159.9251975, 0.02329),
+    ("Dy", 161, 160.9269334, 0.18889),
+    ("Dy", 162, 161.9267984, 0.25475),
+    ("Dy", 163, 162.9287312, 0.24896),
+    ("Dy", 164, 163.9291748, 0.28260),
+    ("Ho", 165, 164.9303221, 1.0),
+    ("Er", 162, 161.928778, 0.00139),
+    ("Er", 164, 163.929200, 0.01601),
+    ("Er", 166, 165.9302931, 0.33503),
+    ("Er", 167, 166.9320482, 0.22869),
+    ("Er", 168, 167.9323702, 0.26978),
+    ("Er", 170, 169.9354643, 0.14910),
+    ("Tm", 169, 168.9342133, 1.0),
+    ("Yb", 168, 167.933897, 0.00123),
+    ("Yb", 170, 169.9347618, 0.02982),
+    ("Yb", 171, 170.9363258, 0.1409),
+    ("Yb", 172, 171.9363815, 0.2168),
+    ("Yb", 173, 172.9382108, 0.16103),
+    ("Yb", 174, 173.9388621, 0.32026),
+    ("Yb", 176, 175.9425717, 0.12996),
+    ("Lu", 175, 174.9407718, 0.97401),
+    ("Lu", 176, 175.9426863, 0.02599),
+    ("Hf", 174, 173.940046, 0.0016),
+    ("Hf", 176, 175.9414086, 0.0526),
+    ("Hf", 177, 176.9432207, 0.1860),
+    ("Hf", 178, 177.9436988, 0.2728),
+    ("Hf", 179, 178.9458161, 0.1362),
+    ("Hf", 180, 179.9465500, 0.3508),
+    ("Ta", 180, 179.9474648, 0.00012),
+    ("Ta", 181, 180.9479958, 0.99988),
+    ("W", 180, 179.946704, 0.0012),
+    ("W", 182, 181.9482042, 0.2650),
+    ("W", 183, 182.9502230, 0.1431),
+    ("W", 184, 183.9509312, 0.3064),
+    ("W", 186, 185.9543641, 0.2843),
+    ("Re", 185, 184.9529550, 0.3740),
+    ("Re", 187, 186.9557531, 0.6260),
+    ("Os", 184, 183.9524891, 0.0002),
+    ("Os", 186, 185.9538382, 0.0159),
+    ("Os", 187, 186.9557505, 0.0196),
+    ("Os", 188, 187.9558382, 0.1324),
+    ("Os", 189, 188.9581475, 0.1615),
+    ("Os", 190, 189.9584470, 0.2626),
+    ("Os", 192, 191.9614807, 0.4078),
+    ("Ir", 191, 190.9605940, 0.373),
+    ("Ir", 193, 192.9629264, 0.627),
+    ("Pt", 190, 189.959932, 0.00014),
+    ("Pt", 192, 191.9610380, 0.00782),
+    ("Pt", 194, 193.9626803, 0.32967),
+    ("Pt", 195, 194.9647911, 0.33832),
+    ("Pt", 196, 195.9649515, 0.25242),
+    ("Pt", 198, 197.967893, 0.07163),
+    ("Au", 197, 196.9665687, 1.0),
+    ("Hg", 196, 195.965833, 0.0015),
+    ("Hg", 198, 197.9667690, 0.0997),
+    ("Hg", 199, 198.9682799, 0.1687),
+    ("Hg", 200, 199.9683260, 0.2310),
+    ("Hg", 201, 200.9703023, 0.1318),
+    ("Hg", 202, 201.9706430, 0.2986),
+    ("Hg", 204, 203.9734939, 0.0687),
+    ("Tl", 203, 202.9723442, 0.2952),
+    ("Tl", 205, 204.9744275, 0.7048),
+    ("Pb", 204, 203.9730436, 0.014),
+    ("Pb", 206, 205.9744653, 0.241),
+    ("Pb", 207, 206.9758969, 0.221),
+    ("Pb", 208, 207.9766521, 0.524),
+    ("Bi", 209, 208.9803987, 1.0),
+    ("Po", 210, 209.9828737, 0.0),
+    ("Rn", 222, 222.0175777, 0.0),
+    ("Ra", 226, 226.0254098, 0.0),
+    ("Th", 232, 232.0380553, 1.0),
+    ("Pa", 231, 231.0358840, 1.0),
+    ("U", 234, 234.0409521, 0.000054),
+    ("U", 235, 235.0439299, 0.007204),
+    ("U", 238, 238.0507882, 0.992742),
+    ("Pu", 239, 239.0521634, 0.0),
+];
+
+fn isotope(entry: &(&'static str, u16, f64, f64)) -> Isotope {
+    let (symbol, mass_number, mass, abundance) = *entry;
+    Isotope {
+        symbol,
+        mass_number,
+        mass,
+        abundance,
+    }
+}
+
+/// Every isotope of an element in the table, lightest first
+pub fn isotopes_of(symbol: &str) -> Vec<Isotope> {
+    ISOTOPES
+        .iter()
+        .filter(|entry| entry.0 == symbol)
+        .map(isotope)
+        .collect()
+}
+
+/// The isotope of an element with the given mass number, if it is known
+pub fn find_isotope(symbol: &str, mass_number: u16) -> Option<Isotope> {
+    ISOTOPES
+        .iter()
+        .find(|entry| entry.0 == symbol && entry.1 == mass_number)
+        .map(isotope)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::isotope::{find_isotope, isotopes_of};
+
+    #[test]
+    fn test_abundances_sum_to_one() {
+        let mut symbols: Vec<&str> =
+            super::ISOTOPES.iter().map(|entry| entry.0).collect();
+        symbols.dedup();
+        for symbol in symbols {
+            let total: f64 =
+                isotopes_of(symbol).iter().map(|i| i.abundance).sum();
+            assert!(
+                total == 0.0 || (total - 1.0).abs() < 1e-3,
+                "{} abundances sum to {}",
+                symbol,
+                total
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_isotope() {
+        assert_eq!(find_isotope("C", 13).unwrap().mass, 13.0033548378);
+        assert_eq!(find_isotope("H", 2).unwrap().abundance, 0.000115);
+        assert!(find_isotope("C", 99).is_none());
+    }
+}
@@ -0,0 +1,372 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use rug::Rational;
+
+use crate::model::{Compound, Element, ElementCounts};
+
+/// The mass of an electron in daltons, lost or gained by an ion
+pub(crate) const ELECTRON_MASS: f64 = 0.000548579909;
+
+/// Peaks closer than this, in daltons, are merged, as a spectrometer at
+/// unit resolution would not separate them
+const MERGE_WIDTH: f64 = 0.01;
+
+/// Peaks below this fraction of the largest are dropped while convolving
+const PRUNE: f64 = 1e-6;
+
+/// Peaks below this percent of the largest are left out of a pattern
+const MIN_INTENSITY: f64 = 0.01;
+
+/// A peak of an isotope pattern, with its intensity in percent of the
+/// largest peak
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    pub mz: f64,
+    pub intensity: f64,
+}
+
+impl Display for Peak {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.4} {:.2}%", self.mz, self.intensity)
+    }
+}
+
+/// Masses and their probabilities, in order of mass
+type Distribution = Vec<(f64, f64)>;
+
+/// The isotopes an atom may be, each with its abundance, or only its label
+fn element_distribution(element: &Element) -> Result<Distribution, String> {
+    if let Some(isotope) = element.isotope() {
+        return Ok(vec![(isotope.mass, 1.0)]);
+    }
+    let distribution: Distribution = element
+        .isotopes()
+        .iter()
+        .filter(|isotope| isotope.abundance > 0.0)
+        .map(|isotope| (isotope.mass, isotope.abundance))
+        .collect();
+    match distribution.is_empty() {
+        true => Err(format!(
+            "{} has no natural isotopes, label one, e.g. [{}{}]",
+            element,
+            element
+                .isotopes()
+                .first()
+                .map_or(0, |isotope| isotope.mass_number),
+            element
+        )),
+        false => Ok(distribution),
+    }
+}
+
+/// Merges peaks closer than `MERGE_WIDTH` at their weighted mass, and
+/// drops the smallest
+fn merge(mut peaks: Distribution) -> Distribution {
+    peaks.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut merged: Distribution = Vec::with_capacity(peaks.len());
+    for (mass, probability) in peaks {
+        match merged.last_mut() {
+            Some((last, total)) if mass - *last < MERGE_WIDTH => {
+                *last = (*last * *total + mass * probability)
+                    / (*total + probability);
+                *total += probability;
+            }
+            _ => merged.push((mass, probability)),
+        }
+    }
+    let largest = merged.iter().map(|(_, p)| *p).fold(0.0, f64::max);
+    merged.retain(|(_, probability)| *probability >= largest * PRUNE);
+    merged
+}
+
+fn convolve(a: &Distribution, b: &Distribution) -> Distribution {
+    merge(
+        a.iter()
+            .flat_map(|(ma, pa)| {
+                b.iter().map(move |(mb, pb)| (ma + mb, pa * pb))
+            })
+            .collect(),
+    )
+}
+
+/// The distribution of `count` atoms, by repeated squaring
+fn power(distribution: &Distribution, count: u32) -> Distribution {
+    let mut result: Distribution = vec![(0.0, 1.0)];
+    let mut base = distribution.clone();
+    let mut count = count;
+    while count > 0 {
+        if count & 1 == 1 {
+            result = convolve(&result, &base);
+        }
+        base = convolve(&base, &base);
+        count >>= 1;
+    }
+    result
+}
+
+/// The whole number of atoms of an element, as needed for a pattern
+fn whole_count(
+    element: &Element,
+    atoms: &ElementCounts,
+) -> Result<u32, String> {
+    let count = &atoms[element];
+    match count.denom() == &1 {
+        true => count.numer().to_u32().ok_or_else(|| {
+            format!("Too many {} atoms for an isotope pattern", element)
+        }),
+        false => Err(format!(
+            "An isotope pattern needs whole numbers of atoms, not {} {}",
+            count, element
+        )),
+    }
+}
+
+/// The mass of the molecule or ion made of the most abundant isotope of
+/// each element, or of the labelled isotope
+pub(crate) fn monoisotopic_mass(
+    atoms: &ElementCounts,
+    charge: i32,
+) -> Result<f64, String> {
+    atoms.iter().try_fold(
+        -(charge as f64) * ELECTRON_MASS,
+        |mass, (element, count)| {
+            let (isotope_mass, _) = element_distribution(element)?
+                .into_iter()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            Ok(mass + isotope_mass * count.to_f64())
+        },
+    )
+}
+
+/// The peaks of the isotope pattern of a molecule or ion, by m/z, from the
+/// natural abundances of its elements
+pub(crate) fn isotope_pattern(
+    atoms: &ElementCounts,
+    charge: i32,
+) -> Result<Vec<Peak>, String> {
+    let mut elements: Vec<&Element> = atoms.keys().collect();
+    elements.sort();
+    let distribution = elements.into_iter().try_fold(
+        vec![(0.0, 1.0)],
+        |distribution, element| {
+            let count = whole_count(element, atoms)?;
+            let atoms = power(&element_distribution(element)?, count);
+            Ok::<Distribution, String>(convolve(&distribution, &atoms))
+        },
+    )?;
+    let largest = distribution.iter().map(|(_, p)| *p).fold(0.0, f64::max);
+    let z = charge.unsigned_abs().max(1) as f64;
+    Ok(distribution
+        .into_iter()
+        .map(|(mass, probability)| Peak {
+            mz: (mass - charge as f64 * ELECTRON_MASS) / z,
+            intensity: probability / largest * 100.0,
+        })
+        .filter(|peak| peak.intensity >= MIN_INTENSITY)
+        .collect())
+}
+
+/// The adduct ions listed when none are chosen
+const DEFAULT_IONS: &[&str] = &[
+    "[M+H]+", "[M+Na]+", "[M+K]+", "[M+NH4]+", "[2M+H]+", "[M+2H]2+",
+    "[M+3H]3+", "[M-H]-", "[M+Cl]-",
+];
+
+/// An ion formed from a molecule in electrospray or APCI, written like
+/// [M+H]+, [2M+Na]+, [M+2H]2+ or [M+H-H2O]+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdductIon {
+    pub notation: String,
+    /// How many molecules the ion holds, e.g. 2 for [2M+H]+
+    pub molecules: u32,
+    /// The atoms gained, or lost where negative
+    pub atoms: ElementCounts,
+    pub charge: i32,
+}
+
+impl AdductIon {
+    /// The common positive and negative mode ions, from [M+H]+ to [M+Cl]-
+    pub fn defaults() -> Vec<AdductIon> {
+        DEFAULT_IONS
+            .iter()
+            .map(|notation| notation.parse().unwrap())
+            .collect()
+    }
+
+    /// The m/z of the ion of a compound, from monoisotopic masses. The
+    /// charge is the ion's, so for an ion like a quaternary ammonium [M]+
+    /// the compound's own charge is already counted.
+    pub fn mz(&self, compound: &Compound) -> Result<f64, String> {
+        let mut atoms = self.atoms.clone();
+        for (element, count) in &compound.atoms {
+            *atoms.entry(*element).or_default() +=
+                Rational::from(count * self.molecules);
+        }
+        if let Some((element, _)) = atoms.iter().find(|(_, count)| **count < 0)
+        {
+            return Err(format!(
+                "{} cannot lose {} from {}",
+                self, element, compound.formula
+            ));
+        }
+        atoms.retain(|_, count| *count != 0);
+        let mass = monoisotopic_mass(&atoms, self.charge)?;
+        Ok(mass / self.charge.unsigned_abs() as f64)
+    }
+}
+
+impl Display for AdductIon {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.notation)
+    }
+}
+
+impl FromStr for AdductIon {
+    type Err = String;
+
+    fn from_str(notation: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid adduct ion {:?}, expected e.g. [M+H]+ or [M+2H]2+",
+                notation
+            )
+        };
+        let (inner, charge) = notation
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .ok_or_else(invalid)?;
+        let magnitude = charge.strip_suffix(['+', '-']).ok_or_else(invalid)?;
+        let sign = match charge.ends_with('+') {
+            true => 1,
+            false => -1,
+        };
+        let charge = match magnitude.is_empty() {
+            true => sign,
+            false => sign * magnitude.parse::<i32>().map_err(|_| invalid())?,
+        };
+        let (molecules, mut rest) =
+            inner.split_once('M').ok_or_else(invalid)?;
+        let molecules = match molecules.is_empty() {
+            true => 1,
+            false => molecules.parse::<u32>().map_err(|_| invalid())?,
+        };
+        let mut atoms = ElementCounts::new();
+        while let Some(sign) = rest.chars().next() {
+            let sign = match sign {
+                '+' => 1,
+                '-' => -1,
+                _ => return Err(invalid()),
+            };
+            let group = &rest[1..];
+            let end = group.find(['+', '-']).unwrap_or(group.len());
+            let digits = group[..end]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(end);
+            let count = match digits {
+                0 => 1,
+                _ => group[..digits].parse::<i32>().map_err(|_| invalid())?,
+            };
+            let gained = Compound::from_formula(&group[digits..end])
+                .map_err(|_| invalid())?;
+            for (element, n) in &gained.atoms {
+                *atoms.entry(*element).or_default() +=
+                    Rational::from(n * (sign * count));
+            }
+            rest = &group[end..];
+        }
+        match (molecules, charge) {
+            (0, _) | (_, 0) => Err(invalid()),
+            _ => Ok(AdductIon {
+                notation: notation.to_string(),
+                molecules,
+                atoms,
+                charge,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::round::half_up;
+
+    use crate::model::{AdductIon, Compound};
+
+    fn compound(formula: &str) -> Compound {
+        Compound::from_formula(formula).unwrap()
+    }
+
+    #[test]
+    fn test_monoisotopic_mass() {
+        let mass = |formula| {
+            half_up(compound(formula).monoisotopic_mass().unwrap(), 6)
+        };
+        assert_eq!(mass("CH4"), 16.0313);
+        assert_eq!(mass("C2H7N"), 45.057849);
+        assert_eq!(mass("C6H12O6"), 180.063388);
+        assert_eq!(mass("CD3OD"), 36.051322);
+        assert_eq!(mass("NH4+"), 18.033826);
+        assert!(compound("TcO4-").monoisotopic_mass().is_err());
+    }
+
+    #[test]
+    fn test_isotope_pattern() {
+        let pattern = compound("CH2Cl2").isotope_pattern().unwrap();
+        let nominal: Vec<(f64, f64)> = pattern
+            .iter()
+            .map(|peak| (peak.mz.round(), half_up(peak.intensity, 1)))
+            .collect();
+        assert_eq!(&nominal[..3], &[(84.0, 100.0), (85.0, 1.1), (86.0, 64.0)]);
+        let glucose = compound("C6H12O6").isotope_pattern().unwrap();
+        assert_eq!(half_up(glucose[0].mz, 4), 180.0634);
+        assert_eq!(half_up(glucose[1].intensity, 1), 6.9);
+        let total: f64 = glucose.iter().map(|peak| peak.intensity).sum();
+        assert!(total < 110.0);
+        assert!(compound("Fe0.95O").isotope_pattern().is_err());
+    }
+
+    #[test]
+    fn test_charged_pattern() {
+        let sulfate = compound("SO4^2-").isotope_pattern().unwrap();
+        assert_eq!(half_up(sulfate[0].mz, 4), 47.9764);
+        assert!((sulfate[1].mz - sulfate[0].mz - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_adduct_ions() {
+        let glucose = compound("C6H12O6");
+        let mz = |notation: &str| {
+            let ion: AdductIon = notation.parse().unwrap();
+            half_up(ion.mz(&glucose).unwrap(), 4)
+        };
+        assert_eq!(mz("[M+H]+"), 181.0707);
+        assert_eq!(mz("[M+Na]+"), 203.0526);
+        assert_eq!(mz("[M+K]+"), 219.0265);
+        assert_eq!(mz("[M+NH4]+"), 198.0972);
+        assert_eq!(mz("[2M+H]+"), 361.1341);
+        assert_eq!(mz("[M+2H]2+"), 91.0390);
+        assert_eq!(mz("[M-H]-"), 179.0561);
+        assert_eq!(mz("[M+Cl]-"), 215.0328);
+        assert_eq!(mz("[M+H-H2O]+"), 163.0601);
+        assert_eq!(AdductIon::defaults().len(), 9);
+    }
+
+    #[test]
+    fn test_invalid_adduct_ions() {
+        for notation in [
+            "M+H",
+            "[M+H]",
+            "[M+Xx]+",
+            "[M+H]0+",
+            "[X+H]+",
+            "[M+H]\u{2212}",
+            "[M+H]²⁺",
+        ] {
+            assert!(notation.parse::<AdductIon>().is_err(), "{}", notation);
+        }
+        let ion: AdductIon = "[M-CO2]-".parse().unwrap();
+        assert!(ion.mz(&compound("H2O")).is_err());
+    }
+}
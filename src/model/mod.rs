@@ -1,21 +1,44 @@
+pub use atomic_weights::{
+    atomic_weights_source, load_atomic_weights, set_atomic_weight,
+    set_atomic_weights, AtomicWeights,
+};
+pub use balance_failure::{BalanceFailure, FailureKind};
 pub use balanced_reaction::BalancedReaction;
-pub use compound::{Compound, ElementCounts};
+pub use batch::balance_many;
+pub use balancings::{Balancings, UnorderedBalance};
+pub(crate) use compound::hill_order;
+pub use compound::{Adduct, Compound, ElementCounts};
 pub use element::Element;
+pub use elemental_analysis::{
+    AnalysisRow, ElementalAnalysis, ANALYSIS_TOLERANCE,
+};
+pub use isotope::Isotope;
+pub use mass_spec::{AdductIon, Peak};
 pub use reactant::Reactant;
+pub(crate) use reactant::decimal_string;
 pub use reaction::Reaction;
 pub use reaction_list::ReactionList;
+pub use redox::{Medium, RedoxBalance};
 pub use sample::Sample;
 pub use theoretical_reaction::TheoreticalReaction;
 pub use theoretical_reaction::YieldUnits;
 pub use units::Units;
 pub use yield_reaction::YieldReaction;
 
+mod atomic_weights;
+mod balance_failure;
 mod balanced_reaction;
+mod balancings;
+mod batch;
 mod compound;
 mod element;
+mod elemental_analysis;
+mod isotope;
+mod mass_spec;
 mod reactant;
 mod reaction;
 mod reaction_list;
+mod redox;
 mod sample;
 mod theoretical_reaction;
 mod units;
@@ -1,51 +1,93 @@
 use std::collections::HashMap;
 
-use crate::model::compound::ElementCounts;
-use crate::model::Compound;
+use rug::Rational;
+
+use crate::model::{Compound, Element};
+
+/// Element counts scaled by (possibly fractional) molar coefficients
+pub type ScaledElementCounts = HashMap<Element, Rational>;
 
 #[derive(Clone, Debug)]
 pub struct Reactant {
     pub compound: Compound,
-    pub molar_coefficient: usize,
+    pub molar_coefficient: Rational,
 }
 
 impl Reactant {
-    pub fn of_compound(compound: Compound, coefficient: usize) -> Self {
+    pub fn of_compound(
+        compound: Compound,
+        coefficient: impl Into<Rational>,
+    ) -> Self {
         Reactant {
             compound,
-            molar_coefficient: coefficient,
+            molar_coefficient: coefficient.into(),
         }
     }
 
     pub fn from_formula(
         formula: &str,
-        coefficient: usize,
+        coefficient: impl Into<Rational>,
     ) -> Result<Self, String> {
         let cmp = Compound::from_formula(formula);
         Ok(Reactant {
             compound: cmp?,
-            molar_coefficient: coefficient,
+            molar_coefficient: coefficient.into(),
         })
     }
 
-    pub fn element_counts(reactants: &[Reactant]) -> ElementCounts {
+    pub fn element_counts(reactants: &[Reactant]) -> ScaledElementCounts {
         let count_pairs = reactants
             .iter()
-            .map(|s| (&s.compound.atoms, s.molar_coefficient));
-        return count_pairs.fold(HashMap::new(), |mut acc, (item, coeff)| {
+            .map(|s| (&s.compound.atoms, &s.molar_coefficient));
+        count_pairs.fold(HashMap::new(), |mut acc, (item, coeff)| {
             for (e, c) in item {
-                let counter = acc.entry(e.clone()).or_insert(0);
-                *counter += *c * coeff;
+                let counter = acc.entry(*e).or_insert_with(Rational::new);
+                *counter += Rational::from(c * coeff);
             }
             acc
-        });
+        })
+    }
+
+    pub fn total_charge(reactants: &[Reactant]) -> Rational {
+        reactants
+            .iter()
+            .map(|r| &r.molar_coefficient * Rational::from(r.compound.charge))
+            .fold(Rational::new(), |acc, c| acc + c)
     }
 
     pub fn format(&self, explicit: bool) -> String {
+        self.format_coefficient(explicit, self.molar_coefficient.to_string())
+    }
+
+    /// Formats the reactant with its coefficient as a decimal, e.g. 12.5*O2
+    pub fn format_decimal(&self, explicit: bool) -> String {
+        self.format_coefficient(
+            explicit,
+            decimal_string(&self.molar_coefficient),
+        )
+    }
+
+    fn format_coefficient(
+        &self,
+        explicit: bool,
+        coefficient: String,
+    ) -> String {
         if self.molar_coefficient != 1 || explicit {
-            format!("{}*{}", self.molar_coefficient, self.compound.formula)
+            format!("{}*{}", coefficient, self.compound.formula)
         } else {
             self.compound.formula.to_string()
         }
     }
 }
+
+/// Formats a rational as a decimal, rounded to at most six places
+pub(crate) fn decimal_string(value: &Rational) -> String {
+    if *value.denom() == 1 {
+        return value.numer().to_string();
+    }
+    let formatted = format!("{:.6}", value.to_f64());
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
@@ -1,7 +1,16 @@
+use std::cmp::Ordering;
 use std::collections::HashSet;
 
-use crate::model::{BalancedReaction, Compound, Element, Reactant};
+use rug::{Integer, Rational};
+
+use crate::model::balance_failure::FailureKind;
+use crate::model::redox;
+use crate::model::{
+    BalanceFailure, BalancedReaction, Balancings, Compound, Element, Medium,
+    Reactant, RedoxBalance, UnorderedBalance,
+};
 use crate::solve;
+use crate::solve::StoichiometricMatrix;
 
 #[derive(Debug, Clone)]
 pub struct Reaction {
@@ -22,6 +31,18 @@ impl Reaction {
         Ok(rxn)
     }
 
+    /// Builds a reaction without checking that every element appears on
+    /// both sides, e.g. a skeleton equation for `balance_redox`
+    pub fn skeleton(
+        reactants: Vec<Compound>,
+        products: Vec<Compound>,
+    ) -> Reaction {
+        Reaction {
+            reactants,
+            products,
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.reactants.len() + self.products.len()
     }
@@ -35,7 +56,7 @@ impl Reaction {
             Reaction::elements_from(&self.reactants);
         let product_atoms: HashSet<&Element> =
             Reaction::elements_from(&self.products);
-        return if !&reagent_atoms.eq(&product_atoms) {
+        if !&reagent_atoms.eq(&product_atoms) {
             let missing_products: HashSet<_> =
                 reagent_atoms.difference(&product_atoms).collect();
             let missing_reagents: HashSet<_> =
@@ -46,60 +67,190 @@ impl Reaction {
             )
         } else {
             Ok(())
-        };
+        }
     }
 
-    pub fn balance(&self) -> Result<BalancedReaction, String> {
-        let mx = solve::build_matrix(
-            &self.all_elements(),
-            self.all_compounds(),
-            self.len(),
-        );
-        let coefficients: Vec<f64> = solve::solve_system(mx, self.len() - 1)?;
+    pub fn balance(&self) -> Result<BalancedReaction, Box<BalanceFailure>> {
+        let mx = self.matrix();
+        let diagnose = || Box::new(BalanceFailure::diagnose(&mx));
+        let coefficients: Vec<Rational> =
+            solve::solve_system(mx.entries.clone(), self.len())
+                .map_err(|_| diagnose())?;
         debug!("Got solution coefficients: {:?}", &coefficients);
-        trace!("Converting to rationals");
-        let scaled_coefficients: Vec<usize> =
-            solve::normalize_coefficients(&coefficients)?;
+        let scaled_coefficients: Vec<Integer> =
+            solve::normalize_coefficients(&coefficients)
+                .map_err(|_| diagnose())?;
+        if scaled_coefficients
+            .iter()
+            .any(|c| c.cmp0() == Ordering::Equal)
+        {
+            return Err(diagnose());
+        }
         let result: Vec<Reactant> = self
             .all_compounds()
             .into_iter()
-            .zip(scaled_coefficients.into_iter())
+            .zip(scaled_coefficients)
             .map(|(c, coefficient)| {
                 Reactant::of_compound(c.clone(), coefficient)
             })
             .collect();
-        Reaction::check_all_nonzero(&result)?;
         let (reagents_result, products_result) =
             result.split_at(self.reactants.len());
         BalancedReaction::new(
             reagents_result.to_vec(),
             products_result.to_vec(),
         )
+        .map_err(|e| Box::new(BalanceFailure::new(FailureKind::Invalid(e))))
+    }
+
+    /// Balances the reaction, then scales it so the pinned species has the
+    /// given (possibly fractional) coefficient, e.g. per mole of fuel
+    pub fn balance_with(
+        &self,
+        pinned: &Compound,
+        coefficient: impl Into<Rational>,
+    ) -> Result<BalancedReaction, String> {
+        self.balance()?.per(pinned, coefficient)
     }
 
-    fn check_all_nonzero(reactants: &[Reactant]) -> Result<(), String> {
-        let zeroes = reactants
+    /// Treats the species as an unordered set: the signs of the solution
+    /// decide which side each species belongs on, oriented to agree with
+    /// the given sides wherever possible (on a tie, the first species is
+    /// kept as a reactant).
+    pub fn balance_unordered(
+        &self,
+    ) -> Result<UnorderedBalance, Box<BalanceFailure>> {
+        let mx = self.matrix();
+        let diagnose = || Box::new(BalanceFailure::diagnose(&mx));
+        let coefficients = solve::solve_system(mx.entries.clone(), self.len())
+            .map_err(|_| diagnose())?;
+        debug!("Got solution coefficients: {:?}", &coefficients);
+        let mut integers = solve::scale_to_integers(&coefficients);
+        if integers.iter().any(|c| c.cmp0() == Ordering::Equal) {
+            return Err(diagnose());
+        }
+        let count = |sign: Ordering| {
+            integers.iter().filter(|i| i.cmp0() == sign).count()
+        };
+        let (agree, disagree) =
+            (count(Ordering::Greater), count(Ordering::Less));
+        let first_disagrees =
+            integers.first().is_some_and(|i| i.cmp0() == Ordering::Less);
+        if disagree > agree || (disagree == agree && first_disagrees) {
+            integers.iter_mut().for_each(|i| *i = Integer::from(-&*i));
+        }
+        let moved = self
+            .all_compounds()
+            .into_iter()
+            .zip(integers.iter())
+            .filter(|(_, c)| c.cmp0() == Ordering::Less)
+            .map(|(c, _)| c.clone())
+            .collect();
+        let balanced = self.place_coefficients(&integers).map_err(|e| {
+            Box::new(BalanceFailure::new(FailureKind::Invalid(e)))
+        })?;
+        Ok(UnorderedBalance::new(balanced, moved))
+    }
+
+    /// Balances a bare set of species, see `balance_unordered`
+    pub fn balance_species(
+        species: Vec<Compound>,
+    ) -> Result<BalancedReaction, Box<BalanceFailure>> {
+        Reaction::skeleton(species, vec![])
+            .balance_unordered()
+            .map(|unordered| unordered.balanced)
+    }
+
+    /// Finds every independent balancing of the reaction, along with the
+    /// minimal sub-reactions that keep every species on its given side.
+    pub fn balance_all(&self) -> Result<Balancings, String> {
+        let mx = self.matrix();
+        let basis = mx.nullspace();
+        debug!("Got nullspace basis: {:?}", &basis);
+        if basis.is_empty() {
+            return Err("Equation has no non-zero solution!".to_string());
+        }
+        let minimal = solve::minimal_solutions(&mx.entries, self.len())?;
+        // prefer a basis of minimal balancings, which keep species on
+        // their given sides, when they span the whole solution space
+        let mut preferred: solve::Matrix = Vec::new();
+        for solution in minimal.iter() {
+            preferred.push(solution.clone());
+            if solve::rank(&preferred, self.len()) < preferred.len() {
+                preferred.pop();
+            }
+        }
+        let basis = if preferred.len() == basis.len() {
+            preferred
+        } else {
+            basis
+        };
+        let basis = basis
+            .iter()
+            .map(|v| self.place_coefficients(&solve::scale_to_integers(v)))
+            .collect::<Result<Vec<BalancedReaction>, String>>()?;
+        let minimal = minimal
             .iter()
-            .filter(|r| r.molar_coefficient == 0)
-            .map(|c| c.compound.formula.as_str())
-            .collect::<Vec<&str>>();
-        if !zeroes.is_empty() {
-            let formulas = zeroes.join(", ");
-            let err_msg = format!(
-                "0 coefficient is not a valid solution! Got 0 for: {}",
-                formulas
-            );
-            return Err(err_msg);
+            .map(|v| self.place_coefficients(&solve::scale_to_integers(v)))
+            .collect::<Result<Vec<BalancedReaction>, String>>()?;
+        Ok(Balancings::new(basis, minimal))
+    }
+
+    /// Balances a skeleton redox reaction by half-reactions, adding water,
+    /// H+ or OH- (depending on the medium) and electrons as needed.
+    pub fn balance_redox(
+        &self,
+        medium: Medium,
+    ) -> Result<RedoxBalance, String> {
+        redox::balance_redox(self, medium)
+    }
+
+    /// The stoichiometric matrix of the reaction, whose nullspace holds
+    /// every balancing
+    pub fn matrix(&self) -> StoichiometricMatrix {
+        StoichiometricMatrix::new(
+            self.reactants.iter().collect(),
+            self.products.iter().collect(),
+        )
+    }
+
+    /// Builds a balanced reaction from signed coefficients, moving species
+    /// with a negative coefficient to the other side and dropping zeroes.
+    fn place_coefficients(
+        &self,
+        coefficients: &[Integer],
+    ) -> Result<BalancedReaction, String> {
+        let mut reactants: Vec<Reactant> = Vec::new();
+        let mut products: Vec<Reactant> = Vec::new();
+        for (i, (compound, coefficient)) in self
+            .all_compounds()
+            .into_iter()
+            .zip(coefficients)
+            .enumerate()
+        {
+            let amount = Integer::from(coefficient.abs_ref());
+            let reactant = Reactant::of_compound(compound.clone(), amount);
+            match (coefficient.cmp0(), i < self.reactants.len()) {
+                (Ordering::Equal, _) => continue,
+                (Ordering::Greater, true) | (Ordering::Less, false) => {
+                    reactants.push(reactant)
+                }
+                _ => products.push(reactant),
+            }
         }
-        Ok(())
+        BalancedReaction::new(reactants, products)
     }
 
+    /// Every element in the reaction, once each, by atomic number
     pub fn all_elements(&self) -> Vec<&Element> {
-        Reaction::elements_from(&self.reactants)
-            .iter()
-            .chain(Reaction::elements_from(&self.products).iter())
-            .cloned()
-            .collect()
+        let mut elements: Vec<&Element> = self
+            .all_compounds()
+            .into_iter()
+            .flat_map(|c| c.all_elements())
+            .collect();
+        elements.sort();
+        elements.dedup();
+        elements
     }
 
     pub fn all_compounds(&self) -> Vec<&Compound> {
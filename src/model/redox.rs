@@ -0,0 +1,471 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use clap::ArgEnum;
+use rug::Integer;
+
+use crate::model::{BalancedReaction, Compound, Element, Reactant, Reaction};
+use crate::solve;
+use crate::solve::StoichiometricMatrix;
+
+/// The medium a redox reaction runs in, which decides whether oxygen and
+/// hydrogen are balanced with H+ or with OH-
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Medium {
+    Acidic,
+    Basic,
+}
+
+#[derive(Debug, Clone)]
+pub struct RedoxBalance {
+    pub overall: BalancedReaction,
+    pub oxidation: BalancedReaction,
+    pub reduction: BalancedReaction,
+    /// Electrons transferred in the overall reaction
+    pub electrons: usize,
+    /// Species left out of the overall reaction because they take no part
+    /// in the electron transfer, e.g. K+ in KMnO4 solutions
+    pub spectators: Vec<Compound>,
+}
+
+/// Species with their coefficients, positive for reactants and negative
+/// for products
+type SignedSpecies = Vec<(Compound, Integer)>;
+
+/// Species with the side they were given on, 1 for reactants and -1 for
+/// products
+type SidedSpecies<'a> = Vec<(&'a Compound, i32)>;
+
+fn species(formula: &str) -> Compound {
+    Compound::from_formula(formula).unwrap_or_else(|e| {
+        panic!("Invalid built-in formula {}: {}", formula, e)
+    })
+}
+
+/// Balances a skeleton redox reaction by the ion-electron method: the
+/// species are split into an oxidation and a reduction half-reaction, each
+/// is balanced with water, H+ and electrons, and the halves are scaled so
+/// the electrons cancel. In basic medium the H+ is then neutralized by
+/// adding OH- to both sides.
+pub(crate) fn balance_redox(
+    reaction: &Reaction,
+    medium: Medium,
+) -> Result<RedoxBalance, String> {
+    let auxiliary = vec![species("H2O"), species("H+"), species("e-")];
+    let hydroxide = species("OH-");
+    let given: SidedSpecies = reaction
+        .reactants
+        .iter()
+        .map(|c| (c, 1))
+        .chain(reaction.products.iter().map(|c| (c, -1)))
+        .filter(|(c, _)| {
+            !auxiliary
+                .iter()
+                .chain(Some(&hydroxide))
+                .any(|a| a.same_species(c))
+        })
+        .collect();
+    let halves: Vec<SignedSpecies> = split_half_reactions(&given)?
+        .iter()
+        .map(|half| balance_half_reaction(half, &auxiliary))
+        .map(|half| match medium {
+            Medium::Acidic => half,
+            Medium::Basic => half.map(|h| neutralize(&h, &auxiliary[1])),
+        })
+        .collect::<Result<Vec<SignedSpecies>, String>>()?;
+    // a half-reaction that transfers no electrons only holds spectators
+    let (spectator_halves, halves): (Vec<_>, Vec<_>) = halves
+        .into_iter()
+        .partition(|h| electrons(h).cmp0() == Ordering::Equal);
+    let mut spectators: Vec<Compound> = vec![];
+    for (compound, _) in spectator_halves.into_iter().flatten() {
+        let auxiliary = auxiliary.iter().chain(Some(&hydroxide));
+        if !auxiliary
+            .chain(&spectators)
+            .any(|c| c.same_species(&compound))
+        {
+            spectators.push(compound);
+        }
+    }
+    if !spectators.is_empty() {
+        let formulas: Vec<&str> =
+            spectators.iter().map(|c| c.formula.as_str()).collect();
+        warn!(
+            "Leaving out {}, which take no part in the electron transfer",
+            formulas.join(", ")
+        );
+    }
+    let (mut reductions, mut oxidations): (Vec<_>, Vec<_>) = halves
+        .into_iter()
+        .partition(|h| electrons(h).cmp0() == Ordering::Greater);
+    if oxidations.len() != 1 || reductions.len() != 1 {
+        return Err(format!(
+            "Expected one oxidation and one reduction half-reaction, got {} and {}",
+            oxidations.len(),
+            reductions.len()
+        ));
+    }
+    let (oxidation, reduction) = (oxidations.remove(0), reductions.remove(0));
+    let released = -electrons(&oxidation);
+    let consumed = electrons(&reduction);
+    let transferred = released.clone().lcm(&consumed);
+    debug!(
+        "Oxidation releases {} electrons, reduction consumes {}, transferring {}",
+        released, consumed, transferred
+    );
+    let mut overall = combine(&[
+        (&oxidation, Integer::from(&transferred / &released)),
+        (&reduction, Integer::from(&transferred / &consumed)),
+    ]);
+    let order = reaction.all_compounds();
+    let rank = |c: &Compound| {
+        order
+            .iter()
+            .position(|o| o.same_species(c))
+            .unwrap_or(order.len())
+    };
+    overall.sort_by_key(|(c, _)| rank(c));
+    let overall = to_balanced(&overall)?;
+    Ok(RedoxBalance {
+        electrons: transferred.to_usize().ok_or_else(|| {
+            format!("Could not convert electron count {}", transferred)
+        })?,
+        overall,
+        oxidation: to_balanced(&oxidation)?,
+        reduction: to_balanced(&reduction)?,
+        spectators,
+    })
+}
+
+/// Groups species that share an element other than hydrogen and oxygen,
+/// species made only of hydrogen and oxygen form their own group.
+/// A single group with one species on one side and two on the other is
+/// split as a disproportionation (or comproportionation).
+fn split_half_reactions<'a>(
+    given: &[(&'a Compound, i32)],
+) -> Result<Vec<SidedSpecies<'a>>, String> {
+    let hydrogen = Element::from_symbol("H");
+    let oxygen = Element::from_symbol("O");
+    let mut groups: Vec<(HashSet<Option<Element>>, SidedSpecies)> = Vec::new();
+    for (compound, side) in given.iter().cloned() {
+        let mut keys: HashSet<Option<Element>> = compound
+            .atoms
+            .keys()
+            .filter(|e| Some(**e) != hydrogen && Some(**e) != oxygen)
+            .map(|e| Some(*e))
+            .collect();
+        if keys.is_empty() {
+            keys.insert(None);
+        }
+        let mut members = Vec::new();
+        let (overlapping, rest): (Vec<_>, Vec<_>) = groups
+            .into_iter()
+            .partition(|(group_keys, _)| !group_keys.is_disjoint(&keys));
+        for (group_keys, group_members) in overlapping {
+            keys.extend(group_keys);
+            members.extend(group_members);
+        }
+        members.push((compound, side));
+        groups = rest;
+        groups.push((keys, members));
+    }
+    let mut halves: Vec<SidedSpecies> =
+        groups.into_iter().map(|(_, members)| members).collect();
+    if halves.len() == 1 {
+        let half = halves.remove(0);
+        let (reactants, products): (Vec<_>, Vec<_>) =
+            half.iter().cloned().partition(|(_, side)| *side > 0);
+        halves = match (reactants.len(), products.len()) {
+            (1, 2) => products.iter().map(|p| vec![reactants[0], *p]).collect(),
+            (2, 1) => reactants.iter().map(|r| vec![*r, products[0]]).collect(),
+            _ => {
+                return Err(
+                    "Could not split the reaction into oxidation and reduction half-reactions, try writing the net ionic equation"
+                        .to_string(),
+                )
+            }
+        };
+    }
+    trace!("Split half-reactions: {:?}", halves);
+    Ok(halves)
+}
+
+/// Balances a half-reaction with the auxiliary species, which may end up
+/// on either side. If that leaves more than one solution, e.g. for H2O2 to
+/// O2, the half-reaction is balanced without water.
+fn balance_half_reaction(
+    half: &[(&Compound, i32)],
+    auxiliary: &[Compound],
+) -> Result<SignedSpecies, String> {
+    balance_with(half, auxiliary)
+        .or_else(|e| match auxiliary.split_first() {
+            Some((_water, rest)) if e.is_ambiguous() => {
+                debug!("Balancing half-reaction without water");
+                balance_with(half, rest)
+            }
+            _ => Err(e),
+        })
+        .map_err(|e| e.message)
+}
+
+struct HalfReactionError {
+    message: String,
+    solutions: usize,
+}
+
+impl HalfReactionError {
+    fn is_ambiguous(&self) -> bool {
+        self.solutions > 1
+    }
+}
+
+fn balance_with(
+    half: &[(&Compound, i32)],
+    auxiliary: &[Compound],
+) -> Result<SignedSpecies, HalfReactionError> {
+    let given_reactants: Vec<&Compound> = half
+        .iter()
+        .filter(|(_, side)| *side > 0)
+        .map(|(c, _)| *c)
+        .collect();
+    let given_products: Vec<&Compound> = half
+        .iter()
+        .filter(|(_, side)| *side < 0)
+        .map(|(c, _)| *c)
+        .collect();
+    let reactants: Vec<&Compound> = given_reactants
+        .iter()
+        .cloned()
+        .chain(auxiliary.iter())
+        .collect();
+    let ncols = reactants.len() + given_products.len();
+    let mx =
+        StoichiometricMatrix::new(reactants.clone(), given_products.clone());
+    let formulas = half
+        .iter()
+        .map(|(c, _)| c.formula.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ");
+    let mut basis = mx.nullspace();
+    if basis.len() != 1 {
+        return Err(HalfReactionError {
+            message: format!(
+                "Half-reaction of {} has {} solutions, expected exactly one",
+                formulas,
+                basis.len()
+            ),
+            solutions: basis.len(),
+        });
+    }
+    let mut coefficients = solve::scale_to_integers(&basis.remove(0));
+    // given reactants, then auxiliary species, then given products
+    let (given, aux) = {
+        let aux_start = given_reactants.len();
+        let aux_end = aux_start + auxiliary.len();
+        let given: Vec<usize> = (0..aux_start).chain(aux_end..ncols).collect();
+        (given, aux_start..aux_end)
+    };
+    if coefficients[given[0]].cmp0() == Ordering::Less {
+        coefficients
+            .iter_mut()
+            .for_each(|c| *c = Integer::from(-&*c));
+    }
+    if given
+        .iter()
+        .any(|&i| coefficients[i].cmp0() != Ordering::Greater)
+    {
+        return Err(HalfReactionError {
+            message: format!(
+                "Half-reaction of {} cannot be balanced with species on the given sides",
+                formulas
+            ),
+            solutions: 1,
+        });
+    }
+    let aux_start = aux.start;
+    let all: Vec<&Compound> =
+        reactants.into_iter().chain(given_products).collect();
+    let signed: SignedSpecies = given
+        .iter()
+        .map(|&i| {
+            let sign = if i < aux_start { 1 } else { -1 };
+            (all[i].clone(), Integer::from(&coefficients[i] * sign))
+        })
+        .chain(aux.map(|i| (all[i].clone(), coefficients[i].clone())))
+        .filter(|(_, x)| x.cmp0() != Ordering::Equal)
+        .collect();
+    trace!("Balanced half-reaction: {:?}", signed);
+    Ok(signed)
+}
+
+/// Converts a half-reaction balanced with H+ to basic medium, adding as
+/// much OH- to both sides, which turns the H+ into water
+fn neutralize(
+    half: &[(Compound, Integer)],
+    proton: &Compound,
+) -> SignedSpecies {
+    let protons = half
+        .iter()
+        .filter(|(c, _)| c.same_species(proton))
+        .fold(Integer::new(), |acc, (_, x)| acc + x);
+    if protons.cmp0() == Ordering::Equal {
+        return half.to_vec();
+    }
+    let water_and_hydroxide: SignedSpecies = vec![
+        (species("H2O"), protons.clone()),
+        (species("OH-"), -protons),
+    ];
+    let without_protons: SignedSpecies = half
+        .iter()
+        .filter(|(c, _)| !c.same_species(proton))
+        .cloned()
+        .collect();
+    combine(&[
+        (&without_protons, Integer::from(1)),
+        (&water_and_hydroxide, Integer::from(1)),
+    ])
+}
+
+/// Net electrons consumed by a half-reaction, negative if it releases them
+fn electrons(half: &[(Compound, Integer)]) -> Integer {
+    half.iter()
+        .filter(|(c, _)| c.atoms.is_empty() && c.charge == -1)
+        .fold(Integer::new(), |acc, (_, x)| acc + x)
+}
+
+/// Adds up scaled half-reactions, cancelling species on both sides
+fn combine(halves: &[(&SignedSpecies, Integer)]) -> SignedSpecies {
+    let mut net: SignedSpecies = Vec::new();
+    for (half, factor) in halves {
+        for (compound, coefficient) in half.iter() {
+            let scaled = Integer::from(coefficient * factor);
+            match net.iter_mut().find(|(c, _)| c.same_species(compound)) {
+                Some((_, total)) => *total += scaled,
+                None => net.push((compound.clone(), scaled)),
+            }
+        }
+    }
+    net.retain(|(_, x)| x.cmp0() != Ordering::Equal);
+    let divisor = net.iter().fold(Integer::new(), |acc, (_, x)| acc.gcd(x));
+    if divisor > 1 {
+        net.iter_mut()
+            .for_each(|(_, x)| *x = Integer::from(&*x / &divisor));
+    }
+    net
+}
+
+fn to_balanced(
+    signed: &[(Compound, Integer)],
+) -> Result<BalancedReaction, String> {
+    let mut reactants: Vec<Reactant> = Vec::new();
+    let mut products: Vec<Reactant> = Vec::new();
+    for (compound, coefficient) in signed {
+        let amount = Integer::from(coefficient.abs_ref());
+        let reactant = Reactant::of_compound(compound.clone(), amount);
+        match coefficient.cmp0() {
+            Ordering::Greater => reactants.push(reactant),
+            _ => products.push(reactant),
+        }
+    }
+    BalancedReaction::new(reactants, products)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skeleton(reactants: Vec<&str>, products: Vec<&str>) -> Reaction {
+        let compounds = |formulas: Vec<&str>| {
+            formulas
+                .into_iter()
+                .map(|f| Compound::from_formula(f).unwrap())
+                .collect()
+        };
+        Reaction::skeleton(compounds(reactants), compounds(products))
+    }
+
+    fn display(reaction: &BalancedReaction) -> String {
+        reaction.display_string(false)
+    }
+
+    #[test]
+    fn permanganate_iron_acidic() {
+        let rxn = skeleton(vec!["MnO4-", "Fe+2"], vec!["Mn+2", "Fe+3"]);
+        let result = rxn.balance_redox(Medium::Acidic).unwrap();
+        assert_eq!(
+            display(&result.overall),
+            "MnO4- + 5*Fe+2 + 8*H+ = Mn+2 + 5*Fe+3 + 4*H2O"
+        );
+        assert_eq!(display(&result.oxidation), "Fe+2 = Fe+3 + e-");
+        assert_eq!(
+            display(&result.reduction),
+            "MnO4- + 8*H+ + 5*e- = Mn+2 + 4*H2O"
+        );
+        assert_eq!(result.electrons, 5);
+    }
+
+    #[test]
+    fn dichromate_acidic() {
+        let rxn = skeleton(vec!["Cr2O7^2-", "Fe+2"], vec!["Cr+3", "Fe+3"]);
+        let result = rxn.balance_redox(Medium::Acidic).unwrap();
+        assert_eq!(
+            display(&result.overall),
+            "Cr2O7^2- + 6*Fe+2 + 14*H+ = 2*Cr+3 + 6*Fe+3 + 7*H2O"
+        );
+    }
+
+    #[test]
+    fn permanganate_iodide_basic() {
+        let rxn = skeleton(vec!["MnO4-", "I-"], vec!["MnO2", "I2"]);
+        let result = rxn.balance_redox(Medium::Basic).unwrap();
+        assert_eq!(
+            display(&result.overall),
+            "2*MnO4- + 6*I- + 4*H2O = 2*MnO2 + 3*I2 + 8*OH-"
+        );
+    }
+
+    #[test]
+    fn peroxide_permanganate() {
+        let rxn = skeleton(vec!["MnO4-", "H2O2"], vec!["Mn+2", "O2"]);
+        let result = rxn.balance_redox(Medium::Acidic).unwrap();
+        assert_eq!(
+            display(&result.overall),
+            "2*MnO4- + 5*H2O2 + 6*H+ = 2*Mn+2 + 5*O2 + 8*H2O"
+        );
+    }
+
+    #[test]
+    fn disproportionation_basic() {
+        let rxn = skeleton(vec!["Cl2"], vec!["Cl-", "ClO3-"]);
+        let result = rxn.balance_redox(Medium::Basic).unwrap();
+        assert_eq!(
+            display(&result.overall),
+            "3*Cl2 + 6*OH- = 5*Cl- + ClO3- + 3*H2O"
+        );
+    }
+
+    #[test]
+    fn spectator_ions() {
+        let rxn = skeleton(vec!["MnO4-", "Na+"], vec!["Mn+2", "Na+"]);
+        assert!(rxn.balance_redox(Medium::Acidic).is_err());
+        let rxn =
+            skeleton(vec!["MnO4-", "Fe+2", "K+"], vec!["Mn+2", "Fe+3", "K+"]);
+        let result = rxn.balance_redox(Medium::Acidic).unwrap();
+        assert_eq!(
+            display(&result.overall),
+            "MnO4- + 5*Fe+2 + 8*H+ = Mn+2 + 5*Fe+3 + 4*H2O"
+        );
+        let spectators: Vec<&str> = result
+            .spectators
+            .iter()
+            .map(|c| c.formula.as_str())
+            .collect();
+        assert_eq!(spectators, vec!["K+"]);
+    }
+
+    #[test]
+    fn no_electron_transfer() {
+        let rxn = skeleton(vec!["Ag+", "Cl-"], vec!["AgCl"]);
+        assert!(rxn.balance_redox(Medium::Acidic).is_err());
+    }
+}
@@ -1,20 +1,22 @@
+use rug::Rational;
+
 use crate::model::Reactant;
 
 #[derive(Clone, Debug)]
 pub struct Sample {
     pub reactant: Reactant,
-    pub mass: f32,
+    pub mass: f64,
 }
 
 impl Sample {
-    pub fn of_reactant(reactant: Reactant, mass: f32) -> Self {
+    pub fn of_reactant(reactant: Reactant, mass: f64) -> Self {
         Sample { reactant, mass }
     }
 
     pub fn from_formula(
         formula: &str,
-        mass: f32,
-        molar_coefficient: usize,
+        mass: f64,
+        molar_coefficient: impl Into<Rational>,
     ) -> Result<Sample, String> {
         let rct = Reactant::from_formula(formula, molar_coefficient);
         Ok(Sample {
@@ -23,11 +25,11 @@ impl Sample {
         })
     }
 
-    pub fn moles(&self) -> f32 {
+    pub fn moles(&self) -> f64 {
         self.mass / self.reactant.compound.molar_mass
     }
 
-    pub fn molrxn(&self) -> f32 {
-        self.moles() / self.reactant.molar_coefficient as f32
+    pub fn molrxn(&self) -> f64 {
+        self.moles() / self.reactant.molar_coefficient.to_f64()
     }
 }
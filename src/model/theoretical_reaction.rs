@@ -23,12 +23,12 @@ impl From<YieldUnits> for Units {
 }
 
 impl TheoreticalReaction {
-    pub fn yields(&self, units: &YieldUnits) -> Vec<(&Reactant, f32)> {
+    pub fn yields(&self, units: &YieldUnits) -> Vec<(&Reactant, f64)> {
         let limiting = yield_reaction::limiting_reagent(&self.reactants);
         self.reaction
             .products
             .iter()
-            .map(|p| yield_reaction::theoretical_yield(&limiting, p))
+            .map(|p| yield_reaction::theoretical_yield(limiting, p))
             .zip(&self.reaction.products)
             .map(|(moles, product)| match units {
                 YieldUnits::Mass => {
@@ -10,24 +10,23 @@ pub struct YieldReaction {
 
 pub fn limiting_reagent(reagents: &[Sample]) -> &Sample {
     reagents
-        .into_iter()
+        .iter()
         .min_by(|l, r| {
             l.molrxn()
                 .partial_cmp(&r.molrxn())
                 .unwrap_or(Ordering::Equal)
         })
-        .map(|s| {
+        .inspect(|s| {
             debug!("Limiting reagent is {}", s.reactant.compound.formula);
-            s
         })
         .unwrap()
 }
 
-pub fn theoretical_yield(limiting: &Sample, product: &Reactant) -> f32 {
+pub fn theoretical_yield(limiting: &Sample, product: &Reactant) -> f64 {
     trace!("{} moles of limiting reagent", limiting.moles());
     let exp_moles = limiting.moles()
-        * (product.molar_coefficient as f32
-            / limiting.reactant.molar_coefficient as f32);
+        * (product.molar_coefficient.to_f64()
+            / limiting.reactant.molar_coefficient.to_f64());
     debug!("Theoretical moles of product: {}", exp_moles);
     exp_moles
 }
@@ -41,13 +40,13 @@ impl YieldReaction {
         limiting_reagent(&self.reagents)
     }
 
-    pub fn theoretical_yield(&self) -> f32 {
+    pub fn theoretical_yield(&self) -> f64 {
         let limiting = self.limiting_reagent();
-        theoretical_yield(&limiting, &self.product.reactant)
+        theoretical_yield(limiting, &self.product.reactant)
             * self.product.reactant.compound.molar_mass
     }
 
-    pub fn percent_yield(&self) -> f32 {
+    pub fn percent_yield(&self) -> f64 {
         self.product.mass / self.theoretical_yield()
     }
 }
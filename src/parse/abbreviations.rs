@@ -0,0 +1,263 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock};
+
+use nom::combinator::all_consuming;
+use nom::error::{Error, ErrorKind};
+use nom::{Err, IResult};
+
+use crate::model::ElementCounts;
+use crate::parse::v2::{parse_formula_v2, plain_group};
+
+/// Common abbreviations for groups, ligands and solvents, with the formula
+/// each one stands for. Ac and Ts shadow actinium and tennessine, and THF
+/// and TBS are never read as tritium compounds, while Pr is left to
+/// praseodymium, so propyl is nPr or iPr.
+const BUILTIN: &[(&str, &str)] = &[
+    ("Me", "CH3"),
+    ("Et", "C2H5"),
+    ("nPr", "C3H7"),
+    ("iPr", "C3H7"),
+    ("Bu", "C4H9"),
+    ("nBu", "C4H9"),
+    ("iBu", "C4H9"),
+    ("sBu", "C4H9"),
+    ("tBu", "C4H9"),
+    ("Cy", "C6H11"),
+    ("Ph", "C6H5"),
+    ("Bn", "C7H7"),
+    ("Bz", "C7H5O"),
+    ("Mes", "C9H11"),
+    ("Tr", "C19H15"),
+    ("Ac", "C2H3O"),
+    ("OAc", "C2H3O2"),
+    ("Piv", "C5H9O"),
+    ("Ts", "C7H7SO2"),
+    ("Ms", "CH3SO2"),
+    ("Tf", "CF3SO2"),
+    ("OTf", "CF3SO3"),
+    ("Boc", "C5H9O2"),
+    ("Cbz", "C8H7O2"),
+    ("Fmoc", "C15H11O2"),
+    ("TMS", "C3H9Si"),
+    ("TBS", "C6H15Si"),
+    ("acac", "C5H7O2"),
+    ("Cp", "C5H5"),
+    ("bpy", "C10H8N2"),
+    ("phen", "C12H8N2"),
+    ("py", "C5H5N"),
+    ("en", "C2H8N2"),
+    ("dba", "C17H14O"),
+    ("dppe", "C26H24P2"),
+    ("dppf", "C34H28FeP2"),
+    ("THF", "C4H8O"),
+    ("DMF", "C3H7NO"),
+    ("DMSO", "C2H6OS"),
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+static USER: RwLock<BTreeMap<String, ElementCounts>> =
+    RwLock::new(BTreeMap::new());
+
+fn builtin() -> &'static BTreeMap<&'static str, ElementCounts> {
+    static TABLE: OnceLock<BTreeMap<&'static str, ElementCounts>> =
+        OnceLock::new();
+    TABLE.get_or_init(|| {
+        BUILTIN
+            .iter()
+            .map(|(abbreviation, formula)| {
+                let (_, atoms) = all_consuming(plain_group)(formula)
+                    .expect("Built-in abbreviations are plain formulas");
+                (*abbreviation, atoms)
+            })
+            .collect()
+    })
+}
+
+/// Turns the expansion of abbreviations in formulas on or off
+pub fn set_abbreviations_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+type Table = BTreeMap<String, ElementCounts>;
+
+/// Reads the atoms an abbreviation of letters stands for
+fn parse_abbreviation(
+    abbreviation: &str,
+    formula: &str,
+) -> Result<ElementCounts, String> {
+    if abbreviation.is_empty()
+        || !abbreviation.chars().all(|c| c.is_ascii_alphabetic())
+    {
+        return Err(format!(
+            "Invalid abbreviation {:?}, must be letters only",
+            abbreviation
+        ));
+    }
+    let parsed = parse_formula_v2(formula).map_err(|e| {
+        format!("Invalid formula for abbreviation {}: {}", abbreviation, e)
+    })?;
+    if parsed.charge != 0 || parsed.atoms.is_empty() {
+        return Err(format!(
+            "Abbreviation {} must stand for neutral atoms, not {}",
+            abbreviation, formula
+        ));
+    }
+    Ok(parsed.atoms)
+}
+
+/// Adds an abbreviation of letters, e.g. Tol for C7H7. It takes precedence
+/// over a built-in one of the same name, and its formula may use other
+/// abbreviations.
+pub fn add_abbreviation(
+    abbreviation: &str,
+    formula: &str,
+) -> Result<(), String> {
+    let atoms = parse_abbreviation(abbreviation, formula)?;
+    debug!("Adding abbreviation {} for {}", abbreviation, formula);
+    USER.write()
+        .expect("Abbreviation table poisoned")
+        .insert(abbreviation.to_string(), atoms);
+    Ok(())
+}
+
+/// Reads the abbreviations in a JSON object file, e.g. {"Tol": "C7H7"}
+fn read_abbreviations(path: &str) -> Result<Table, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let table: BTreeMap<String, String> = serde_json::from_str(&contents)
+        .map_err(|e| format!("Could not parse {}: {}", path, e))?;
+    table
+        .into_iter()
+        .map(|(abbreviation, formula)| {
+            let atoms = parse_abbreviation(&abbreviation, &formula)?;
+            Ok((abbreviation, atoms))
+        })
+        .collect()
+}
+
+/// Adds the abbreviations in a JSON object file, e.g. {"Tol": "C7H7"},
+/// returning how many were added. None are added if any is invalid.
+pub fn load_abbreviations(path: &str) -> Result<usize, String> {
+    let table = read_abbreviations(path)?;
+    let added = table.len();
+    debug!("Adding {} abbreviations from {}", added, path);
+    USER.write()
+        .expect("Abbreviation table poisoned")
+        .extend(table);
+    Ok(added)
+}
+
+/// The longest abbreviation, of the user's or built in, that the input
+/// starts with, and the input after it
+fn longest<'a>(
+    input: &'a str,
+    user: &Table,
+) -> Option<(&'a str, ElementCounts)> {
+    user.iter()
+        .map(|(abbreviation, atoms)| (abbreviation.as_str(), atoms))
+        .chain(
+            builtin()
+                .iter()
+                .map(|(abbreviation, atoms)| (*abbreviation, atoms)),
+        )
+        .filter(|(abbreviation, _)| input.starts_with(abbreviation))
+        // the first longest wins, so user entries win ties
+        .min_by_key(|(abbreviation, _)| Reverse(abbreviation.len()))
+        .map(|(abbreviation, atoms)| {
+            (&input[abbreviation.len()..], atoms.clone())
+        })
+}
+
+/// Parses the longest abbreviation at the start of the input into its atoms
+pub(crate) fn abbreviation(input: &str) -> IResult<&str, ElementCounts> {
+    let no_match = || Err(Err::Error(Error::new(input, ErrorKind::Tag)));
+    if !ENABLED.load(Ordering::Relaxed) {
+        return no_match();
+    }
+    let user = USER.read().expect("Abbreviation table poisoned");
+    match longest(input, &user) {
+        Some(found) => Ok(found),
+        None => no_match(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::model::Compound;
+    use crate::parse::abbreviations::{
+        longest, parse_abbreviation, read_abbreviations,
+    };
+
+    fn same_atoms(abbreviated: &str, spelled_out: &str) {
+        let abbreviated = Compound::from_formula(abbreviated).unwrap();
+        let spelled_out = Compound::from_formula(spelled_out).unwrap();
+        assert_eq!(
+            abbreviated.atoms, spelled_out.atoms,
+            "{}",
+            abbreviated.formula
+        );
+    }
+
+    #[test]
+    fn test_builtin() {
+        same_atoms("PhCOOH", "C7H6O2");
+        same_atoms("Et3N", "C6H15N");
+        same_atoms("Pd(OAc)2", "PdC4H6O4");
+        same_atoms("TsCl", "C7H7SO2Cl");
+        same_atoms("tBuOK", "C4H9OK");
+        same_atoms("V(acac)3", "VC15H21O6");
+        same_atoms("Boc2O", "C10H18O5");
+        same_atoms("[Co(en)3]Cl3", "CoC6H24N6Cl3");
+        same_atoms("BF3·OEt2", "BF3C4H10O");
+    }
+
+    #[test]
+    fn test_elements_kept() {
+        same_atoms("PrCl3", "Cl3Pr");
+        same_atoms("NaOAc", "C2H3NaO2");
+        same_atoms("Co(CO)4", "CoC4O4");
+    }
+
+    #[test]
+    fn test_tritium_shadowed() {
+        // THF and TBS are read as abbreviations, not tritium compounds
+        same_atoms("THF", "C4H8O");
+        same_atoms("TBSCl", "C6H15SiCl");
+        same_atoms("HTF", "[3H]HF");
+        assert_ne!(
+            Compound::from_formula("HTF").unwrap().atoms,
+            Compound::from_formula("THF").unwrap().atoms
+        );
+    }
+
+    #[test]
+    fn test_user_abbreviations() {
+        let atoms = |formula| Compound::from_formula(formula).unwrap().atoms;
+        let mut user = BTreeMap::new();
+        user.insert(
+            "Tol".to_string(),
+            parse_abbreviation("Tol", "MeC6H4").unwrap(),
+        );
+        assert_eq!(longest("TolSO2Cl", &user), Some(("SO2Cl", atoms("C7H7"))));
+        assert!(parse_abbreviation("T0l", "C7H7").is_err());
+        assert!(parse_abbreviation("Xyz", "C7H7+").is_err());
+        // user entries take precedence over built-in ones
+        user.insert(
+            "Ph".to_string(),
+            parse_abbreviation("Ph", "C6H4F").unwrap(),
+        );
+        assert_eq!(longest("PhOH", &user), Some(("OH", atoms("C6H4F"))));
+        let path = std::env::temp_dir().join("stoichkit_abbreviations.json");
+        std::fs::write(&path, r#"{"Nf": "C4F9SO2", "Anis": "C7H7O"}"#).unwrap();
+        let loaded = read_abbreviations(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(longest("AnisNf", &loaded), Some(("Nf", atoms("C7H7O"))));
+        std::fs::write(&path, r#"{"Nf": "C4F9SO2", "A1": "C7H7O"}"#).unwrap();
+        assert!(read_abbreviations(path.to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use nom::branch::alt;
+use nom::character::complete::{char, digit1};
+use nom::combinator::{all_consuming, map_res, recognize};
+use nom::sequence::{separated_pair, tuple};
+use nom::IResult;
+use rug::{Integer, Rational};
+
+/// Parses a decimal coefficient, e.g. 12.5
+pub(crate) fn decimal(decimal: &str) -> IResult<&str, Rational> {
+    map_res(recognize(tuple((digit1, char('.'), digit1))), |s: &str| {
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let numerator = format!("{}{}", whole, fraction).parse::<Integer>();
+        numerator.map(|n| {
+            let denominator =
+                Integer::from(Integer::u_pow_u(10, fraction.len() as u32));
+            Rational::from((n, denominator))
+        })
+    })(decimal)
+}
+
+/// Parses a fractional coefficient, e.g. 25/2
+fn fraction(fraction: &str) -> IResult<&str, Rational> {
+    map_res(
+        separated_pair(digit1, char('/'), digit1),
+        |(n, d): (&str, &str)| {
+            let (n, d) = (n.parse::<Integer>(), d.parse::<Integer>());
+            match (n, d) {
+                (Ok(_), Ok(d)) if d == 0 => Err("zero denominator".to_string()),
+                (Ok(n), Ok(d)) => Ok(Rational::from((n, d))),
+                _ => Err("invalid fraction".to_string()),
+            }
+        },
+    )(fraction)
+}
+
+/// Parses an integer coefficient, e.g. 2
+pub(crate) fn integer(integer: &str) -> IResult<&str, Rational> {
+    map_res(digit1, |s: &str| s.parse::<Integer>().map(Rational::from))(integer)
+}
+
+/// Parses a molar coefficient, which may be an integer, a fraction or a
+/// decimal, e.g. 2, 25/2 or 12.5
+pub fn coefficient(coefficient: &str) -> IResult<&str, Rational> {
+    alt((decimal, fraction, integer))(coefficient)
+}
+
+/// Parse a molar coefficient string.
+/// # Examples
+/// parse_coefficient("2")
+/// parse_coefficient("25/2")
+/// parse_coefficient("12.5")
+pub fn parse_coefficient(input: &str) -> Result<Rational, String> {
+    all_consuming(coefficient)(input)
+        .map(|(_, c)| c)
+        .map_err(|_| format!("Invalid coefficient {:?}", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::Rational;
+
+    use crate::parse::parse_coefficient;
+
+    #[test]
+    fn test_coefficients() {
+        assert_eq!(parse_coefficient("2").unwrap(), 2);
+        assert_eq!(parse_coefficient("25/2").unwrap(), Rational::from((25, 2)));
+        assert_eq!(parse_coefficient("12.5").unwrap(), Rational::from((25, 2)));
+        assert_eq!(parse_coefficient("0.1").unwrap(), Rational::from((1, 10)));
+    }
+
+    #[test]
+    fn test_invalid_coefficients() {
+        assert!(parse_coefficient("1/0").is_err());
+        assert!(parse_coefficient("2x").is_err());
+        assert!(parse_coefficient("").is_err());
+    }
+}
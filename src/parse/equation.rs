@@ -0,0 +1,490 @@
+use std::fmt::{Display, Formatter};
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till1};
+use nom::character::complete::{char, digit1, satisfy, space0};
+use nom::combinator::{map, map_res, not, opt, peek, recognize};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::IResult;
+use rug::Rational;
+
+use crate::model::{BalancedReaction, Compound, Reactant, Reaction, Sample};
+use crate::parse::coefficient::coefficient;
+use crate::parse::inchi::INCHI_PREFIX;
+use crate::parse::normalize::normalize;
+use crate::parse::smiles::SMILES_PREFIX;
+use crate::parse::v2::{formula_parser, parse_formula_v2};
+
+/// The arrow between the two sides of an equation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrow {
+    /// =
+    Equals,
+    /// -> or →
+    Forward,
+    /// <=> or ⇌
+    Equilibrium,
+}
+
+/// A species in an equation, e.g. 2*H2O 4.5, with its byte span in the
+/// equation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub coefficient: Option<Rational>,
+    pub formula: String,
+    /// Mass in grams, written after the formula
+    pub amount: Option<f64>,
+    pub span: (usize, usize),
+}
+
+/// A parsed equation. Without an arrow, every species is a reactant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equation {
+    pub reactants: Vec<Term>,
+    pub products: Vec<Term>,
+    pub arrow: Option<Arrow>,
+    input: String,
+    normalized_from: Option<String>,
+}
+
+/// An equation that could not be parsed, pointing at the offending byte
+/// of the equation as normalized, see `normalize`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EquationError {
+    pub input: String,
+    pub offset: usize,
+    pub message: String,
+    /// The equation as written, when normalizing changed it
+    pub normalized_from: Option<String>,
+}
+
+impl Display for EquationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let column = self.input[..self.offset].chars().count();
+        write!(
+            f,
+            "{} at column {}\n  {}\n  {}^",
+            self.message,
+            column + 1,
+            self.input,
+            " ".repeat(column)
+        )?;
+        match &self.normalized_from {
+            Some(original) => write!(f, "\n  normalized from {}", original),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<EquationError> for String {
+    fn from(error: EquationError) -> Self {
+        error.to_string()
+    }
+}
+
+impl Equation {
+    fn error(&self, term: &Term, message: String) -> EquationError {
+        EquationError {
+            input: self.input.clone(),
+            offset: term.span.0,
+            message,
+            normalized_from: self.normalized_from.clone(),
+        }
+    }
+
+    fn compound(&self, term: &Term) -> Result<Compound, EquationError> {
+        Compound::new(&term.formula).map_err(|e| {
+            let formula_start = term.span.0
+                + self.input[term.span.0..].find(&term.formula).unwrap_or(0);
+            EquationError {
+                input: self.input.clone(),
+                offset: formula_start + e.offset,
+                message: format!(
+                    "Invalid formula {}: {}",
+                    term.formula,
+                    e.describe()
+                ),
+                normalized_from: self.normalized_from.clone(),
+            }
+        })
+    }
+
+    fn reactant(&self, term: &Term) -> Result<Reactant, EquationError> {
+        let coefficient = term.coefficient.clone().unwrap_or_else(|| 1.into());
+        Ok(Reactant::of_compound(self.compound(term)?, coefficient))
+    }
+
+    /// The amount of a species as a sample of the reactant
+    pub fn sample(&self, term: &Term) -> Result<Sample, EquationError> {
+        match term.amount {
+            Some(mass) => Ok(Sample::of_reactant(self.reactant(term)?, mass)),
+            None => Err(self.error(
+                term,
+                format!("Got substance with no mass: {}", term.formula),
+            )),
+        }
+    }
+
+    /// The species as an unbalanced reaction, ignoring any coefficients.
+    /// Elements are not checked, so a failed balance can explain them.
+    pub fn reaction(&self) -> Result<Reaction, EquationError> {
+        let compounds = |terms: &[Term]| {
+            terms
+                .iter()
+                .map(|t| self.compound(t))
+                .collect::<Result<Vec<Compound>, EquationError>>()
+        };
+        Ok(Reaction::skeleton(
+            compounds(&self.reactants)?,
+            compounds(&self.products)?,
+        ))
+    }
+
+    /// The equation with its coefficients (1 where none is written), which
+    /// must already be balanced
+    pub fn balanced_reaction(&self) -> Result<BalancedReaction, String> {
+        let reactants = |terms: &[Term]| {
+            terms
+                .iter()
+                .map(|t| self.reactant(t))
+                .collect::<Result<Vec<Reactant>, EquationError>>()
+        };
+        BalancedReaction::new(
+            reactants(&self.reactants)?,
+            reactants(&self.products)?,
+        )
+    }
+}
+
+/// Parses a reaction arrow
+fn arrow(arrow: &str) -> IResult<&str, Arrow> {
+    alt((
+        map(alt((tag("<=>"), tag("⇌"))), |_| Arrow::Equilibrium),
+        map(alt((tag("->"), tag("→"))), |_| Arrow::Forward),
+        map(tag("="), |_| Arrow::Equals),
+    ))(arrow)
+}
+
+/// Parses a coefficient before a formula, e.g. the 2 in 2H2O, 2 H2O or
+/// 2*H2O
+fn leading_coefficient(input: &str) -> IResult<&str, Rational> {
+    terminated(
+        coefficient,
+        alt((delimited(space0, tag("*"), space0), space0)),
+    )(input)
+}
+
+/// Parses an amount in grams after a formula, e.g. the 4.0 in H2O2 4.0.
+/// A number directly followed by a formula or * is the next coefficient.
+fn amount(input: &str) -> IResult<&str, f64> {
+    preceded(
+        space0,
+        terminated(
+            map_res(
+                recognize(pair(digit1, opt(pair(char('.'), digit1)))),
+                |s: &str| s.parse::<f64>(),
+            ),
+            not(peek(satisfy(|c| {
+                c.is_alphanumeric() || "*/.([{".contains(c)
+            }))),
+        ),
+    )(input)
+}
+
+/// Parses a species written as SMILES or InChI, e.g. smiles:CC(=O)O or
+/// InChI=1S/CH4/h1H4, which runs to the next space since either may hold
+/// + and =
+fn notation(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        alt((tag(SMILES_PREFIX), tag(INCHI_PREFIX))),
+        take_till1(|c: char| c.is_whitespace()),
+    ))(input)
+}
+
+/// Parses a species, with an optional coefficient and amount
+fn term(input: &str) -> IResult<&str, (Option<Rational>, &str, Option<f64>)> {
+    tuple((
+        opt(leading_coefficient),
+        alt((notation, recognize(formula_parser))),
+        opt(preceded(char(' '), amount)),
+    ))(input)
+}
+
+/// Explains why the text at `rest` is not a species, pointing into the
+/// formula where possible
+fn invalid_species(input: &str, rest: &str) -> EquationError {
+    let formula = leading_coefficient(rest).map_or(rest, |(r, _)| r);
+    let token = formula.split_whitespace().next().unwrap_or("");
+    let offset = input.len() - formula.len();
+    match parse_formula_v2(token) {
+        Err(e) => EquationError {
+            input: input.to_string(),
+            offset: offset + e.offset,
+            message: format!("Invalid formula {}: {}", token, e.describe()),
+            normalized_from: None,
+        },
+        Ok(_) => EquationError {
+            input: input.to_string(),
+            offset,
+            message: format!("Invalid species {:?}", token),
+            normalized_from: None,
+        },
+    }
+}
+
+/// Parse a chemical equation, with species separated by + or whitespace
+/// and the sides by =, ->, →, <=> or ⇌. Unicode and HTML notation is
+/// normalized first, and errors point into the normalized equation.
+/// # Examples
+/// parse_equation("2H2 + O2 -> 2H2O")
+/// parse_equation("H2 + O2 → H2O")
+/// parse_equation("2*H2O2 4.0 = 2*H2O O2")
+pub fn parse_equation(input: &str) -> Result<Equation, EquationError> {
+    let normalized = normalize(input);
+    let normalized_from = match normalized == input {
+        true => None,
+        false => Some(input.to_string()),
+    };
+    match parse_normalized(&normalized) {
+        Ok(equation) => Ok(Equation {
+            normalized_from,
+            ..equation
+        }),
+        Err(error) => Err(EquationError {
+            normalized_from,
+            ..error
+        }),
+    }
+}
+
+/// Parses an equation already in plain notation
+fn parse_normalized(input: &str) -> Result<Equation, EquationError> {
+    let error = |rest: &str, message: String| EquationError {
+        input: input.to_string(),
+        offset: input.len() - rest.len(),
+        message,
+        normalized_from: None,
+    };
+    let mut sides: Vec<Vec<Term>> = vec![vec![]];
+    let mut arrow_found: Option<Arrow> = None;
+    let mut rest = input.trim_start();
+    loop {
+        if rest.is_empty() {
+            return Err(error(rest, "Expected a species".to_string()));
+        }
+        let (after, (coefficient, formula, amount)) =
+            term(rest).map_err(|_| {
+                match rest.starts_with('+') || arrow(rest).is_ok() {
+                    true => error(rest, "Expected a species".to_string()),
+                    false => invalid_species(input, rest),
+                }
+            })?;
+        let start = input.len() - rest.len();
+        let end = input.len() - after.len();
+        trace!("Parsed species {:?} at {}..{}", formula, start, end);
+        sides.last_mut().unwrap().push(Term {
+            coefficient,
+            formula: formula.to_string(),
+            amount,
+            span: (start, end),
+        });
+        let trimmed = after.trim_start();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Ok((next, _)) = char::<&str, ()>('+')(trimmed) {
+            rest = next.trim_start();
+        } else if let Ok((next, found)) = arrow(trimmed) {
+            if arrow_found.is_some() {
+                return Err(error(
+                    trimmed,
+                    "Equation has more than one arrow".to_string(),
+                ));
+            }
+            arrow_found = Some(found);
+            sides.push(vec![]);
+            rest = next.trim_start();
+        } else if trimmed.len() < after.len() {
+            rest = trimmed;
+        } else {
+            let term_start = &input[start..];
+            return Err(invalid_species(input, term_start));
+        }
+    }
+    let products = match sides.len() {
+        2 => sides.pop().unwrap(),
+        _ => vec![],
+    };
+    Ok(Equation {
+        reactants: sides.pop().unwrap(),
+        products,
+        arrow: arrow_found,
+        input: input.to_string(),
+        normalized_from: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::Rational;
+
+    use super::*;
+
+    fn formulas(terms: &[Term]) -> Vec<&str> {
+        terms.iter().map(|t| t.formula.as_str()).collect()
+    }
+
+    #[test]
+    fn test_arrows() {
+        for input in [
+            "H2 + O2 = H2O",
+            "H2 + O2 -> H2O",
+            "H2 + O2 → H2O",
+            "H2 + O2 <=> H2O",
+            "H2 + O2 ⇌ H2O",
+            "H2 O2 = H2O",
+            "H2+O2->H2O",
+        ] {
+            let equation = parse_equation(input).unwrap();
+            assert_eq!(formulas(&equation.reactants), vec!["H2", "O2"]);
+            assert_eq!(formulas(&equation.products), vec!["H2O"]);
+        }
+        assert_eq!(
+            parse_equation("H2 ⇌ H2").unwrap().arrow,
+            Some(Arrow::Equilibrium)
+        );
+    }
+
+    #[test]
+    fn test_coefficients() {
+        for input in
+            ["2H2 + O2 -> 2H2O", "2 H2 + O2 -> 2 H2O", "2*H2 O2 = 2*H2O"]
+        {
+            let equation = parse_equation(input).unwrap();
+            let coefficients: Vec<Option<Rational>> = equation
+                .reactants
+                .iter()
+                .chain(equation.products.iter())
+                .map(|t| t.coefficient.clone())
+                .collect();
+            assert_eq!(
+                coefficients,
+                vec![Some(Rational::from(2)), None, Some(Rational::from(2))],
+                "{}",
+                input
+            );
+        }
+        let equation = parse_equation("C8H18 + 25/2 O2 = 8CO2 + 9H2O").unwrap();
+        assert_eq!(
+            equation.reactants[1].coefficient,
+            Some(Rational::from((25, 2)))
+        );
+        assert!(equation.balanced_reaction().is_ok());
+    }
+
+    #[test]
+    fn test_ions() {
+        let equation =
+            parse_equation("MnO4- + 5Fe+2 + 8H+ -> Mn+2 + 5Fe+3 + 4H2O")
+                .unwrap();
+        assert_eq!(formulas(&equation.reactants), vec!["MnO4-", "Fe+2", "H+"]);
+        assert_eq!(formulas(&equation.products), vec!["Mn+2", "Fe+3", "H2O"]);
+        let equation = parse_equation("H+ + OH- = H2O").unwrap();
+        assert_eq!(formulas(&equation.reactants), vec!["H+", "OH-"]);
+        let equation = parse_equation("H2O->H2O2").unwrap();
+        assert_eq!(formulas(&equation.reactants), vec!["H2O"]);
+    }
+
+    #[test]
+    fn test_amounts() {
+        let equation = parse_equation("2*H2O2 4.0 = 2*H2O O2").unwrap();
+        assert_eq!(equation.reactants[0].amount, Some(4.0));
+        assert_eq!(equation.products[0].amount, None);
+        let equation = parse_equation("C4H6 0.7254").unwrap();
+        assert_eq!(equation.arrow, None);
+        assert_eq!(equation.reactants[0].amount, Some(0.7254));
+        let equation = parse_equation("H2 2 + O2 16 = H2O").unwrap();
+        assert_eq!(equation.reactants[1].amount, Some(16.0));
+        let equation = parse_equation("H2 2 O2").unwrap();
+        assert_eq!(equation.reactants[0].amount, Some(2.0));
+        assert_eq!(equation.reactants.len(), 2);
+        let equation = parse_equation("H2 2*O2").unwrap();
+        assert_eq!(equation.reactants[0].amount, None);
+    }
+
+    #[test]
+    fn test_spans() {
+        let equation = parse_equation("2H2 + O2 -> 2H2O").unwrap();
+        let spans: Vec<(usize, usize)> = equation
+            .reactants
+            .iter()
+            .chain(equation.products.iter())
+            .map(|t| t.span)
+            .collect();
+        assert_eq!(spans, vec![(0, 3), (6, 8), (12, 16)]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = parse_equation("H2 + Xy2 = H2O").unwrap_err();
+        assert_eq!(error.offset, 5);
+        assert_eq!(
+            error.message,
+            "Invalid formula Xy2: unexpected \"Xy\", expected element symbol, (, [, { or e-"
+        );
+        let error = parse_equation("H2 + O2 =").unwrap_err();
+        assert_eq!(error.offset, 9);
+        let error = parse_equation("H2 = O2 = H2O").unwrap_err();
+        assert_eq!(error.offset, 8);
+        let error = parse_equation("H2 + O2$ = H2O").unwrap_err();
+        assert_eq!(error.offset, 7);
+        assert_eq!(
+            error.to_string(),
+            "Invalid formula O2$: unexpected \"$\", expected element symbol, count, (, [, {, charge or * at column 8\n  H2 + O2$ = H2O\n         ^"
+        );
+        let error = parse_equation("H2 + CL2 = HCl").unwrap_err();
+        assert_eq!(error.offset, 6);
+        assert!(error.message.ends_with("(did you mean Cl2?)"));
+    }
+
+    #[test]
+    fn test_pasted_text() {
+        let equation =
+            parse_equation("MnO₄⁻ + 8H⁺ + 5e⁻ ⟶ Mn²⁺ + 4H₂O").unwrap();
+        assert_eq!(equation.arrow, Some(Arrow::Forward));
+        assert_eq!(equation.products[0].formula, "Mn^2+");
+        let reaction = equation.balanced_reaction().unwrap();
+        assert_eq!(reaction.reactants[0].compound.charge, -1);
+        assert_eq!(reaction.products[0].compound.charge, 2);
+        let error = parse_equation("H₂ + O₂$ → H₂O").unwrap_err();
+        assert_eq!(error.input, "H2 + O2$ → H2O");
+        assert_eq!(error.offset, 7);
+        assert_eq!(
+            error.to_string(),
+            "Invalid formula O2$: unexpected \"$\", expected element symbol, count, (, [, {, charge or * at column 8\n  H2 + O2$ → H2O\n         ^\n  normalized from H₂ + O₂$ → H₂O"
+        );
+        let equation = parse_equation("H₂ + smiles:C1CC → H₂").unwrap();
+        let error = equation.reaction().unwrap_err();
+        assert_eq!(error.offset, 16);
+        assert_eq!(
+            error.normalized_from.as_deref(),
+            Some("H₂ + smiles:C1CC → H₂")
+        );
+    }
+
+    #[test]
+    fn test_smiles_and_inchi() {
+        let equation =
+            parse_equation("smiles:CCO 4.6 + 3O2 = 2CO2 + 3smiles:O").unwrap();
+        assert_eq!(formulas(&equation.reactants), vec!["smiles:CCO", "O2"]);
+        assert_eq!(equation.reactants[0].amount, Some(4.6));
+        assert!(equation.balanced_reaction().is_ok());
+        let equation =
+            parse_equation("InChI=1S/CH4/h1H4 + 2O2 = CO2 + 2H2O").unwrap();
+        assert_eq!(equation.reactants[0].formula, "InChI=1S/CH4/h1H4");
+        assert!(equation.balanced_reaction().is_ok());
+        let equation = parse_equation("smiles:C1CC + O2 = CO2").unwrap();
+        let error = equation.reaction().unwrap_err();
+        assert_eq!(error.offset, 11);
+    }
+}
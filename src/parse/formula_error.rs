@@ -0,0 +1,297 @@
+use std::fmt::{Display, Formatter};
+
+use crate::parse::v2::{element_from_string, formula_parser};
+
+/// A formula that could not be parsed entirely, pointing at the byte where
+/// parsing stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaError {
+    pub input: String,
+    pub offset: usize,
+    /// The text at the offset, empty at the end of the formula
+    pub token: String,
+    pub expected: Vec<String>,
+    /// Formulas with the letters around the offset re-cased, e.g. Cl for CL
+    pub suggestions: Vec<String>,
+}
+
+impl FormulaError {
+    /// Finds where and why a formula fails to parse
+    pub(crate) fn locate(input: &str) -> FormulaError {
+        let (offset, close) = stop_offset(input, 0, input.len());
+        let token: String = match input[offset..].chars().next() {
+            None => String::new(),
+            // a symbol-like token, or a run of lower case letters
+            Some(c) if c.is_ascii_alphabetic() => std::iter::once(c)
+                .chain(
+                    input[offset..]
+                        .chars()
+                        .skip(1)
+                        .take_while(|c| c.is_ascii_lowercase()),
+                )
+                .collect(),
+            Some(c) => c.to_string(),
+        };
+        let expected: Vec<String> = match (close, offset) {
+            (Some(close), _) => vec![close.to_string()],
+            (None, 0) => vec!["element symbol", "(", "[", "{", "e-"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            (None, _) => {
+                let mut expected =
+                    vec!["element symbol", "count", "(", "[", "{"];
+                match open_bracket(&input[..offset]) {
+                    Some(open) => expected.push(closing(open)),
+                    None => expected.extend(["charge", "*"]),
+                };
+                expected.into_iter().map(String::from).collect()
+            }
+        };
+        FormulaError {
+            input: input.to_string(),
+            offset,
+            suggestions: suggestions(input, offset),
+            token,
+            expected,
+        }
+    }
+
+    /// An error at `offset` where something else was expected, for notations
+    /// like SMILES that are not read by the formula parser
+    pub(crate) fn expected(
+        input: &str,
+        offset: usize,
+        expected: &str,
+    ) -> FormulaError {
+        FormulaError {
+            input: input.to_string(),
+            offset,
+            token: input[offset..]
+                .chars()
+                .next()
+                .map(String::from)
+                .unwrap_or_default(),
+            expected: vec![expected.to_string()],
+            suggestions: vec![],
+        }
+    }
+
+    /// Describes the error without the formula, e.g. for an equation
+    pub fn describe(&self) -> String {
+        let unexpected = match self.token.is_empty() {
+            true => "unexpected end of formula".to_string(),
+            false => format!("unexpected {:?}", self.token),
+        };
+        let expected = match self.expected.split_last() {
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => String::new(),
+        };
+        match self.suggestions.is_empty() {
+            true => format!("{}, expected {}", unexpected, expected),
+            false => format!(
+                "{}, expected {} (did you mean {}?)",
+                unexpected,
+                expected,
+                self.suggestions.join(" or ")
+            ),
+        }
+    }
+}
+
+impl Display for FormulaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let column = self.input[..self.offset].chars().count();
+        write!(
+            f,
+            "Invalid formula {:?}: {} at column {}\n  {}\n  {}^",
+            self.input,
+            self.describe(),
+            column + 1,
+            self.input,
+            " ".repeat(column)
+        )
+    }
+}
+
+impl From<FormulaError> for String {
+    fn from(error: FormulaError) -> Self {
+        error.to_string()
+    }
+}
+
+/// The offset in `input[start..end]` where parsing stops, looking inside a
+/// bracket that could not be parsed, along with the closing bracket that
+/// was expected there, if any
+fn stop_offset(input: &str, start: usize, end: usize) -> (usize, Option<char>) {
+    let rest = &input[start..end];
+    let consumed = match formula_parser(rest) {
+        Ok((remainder, _)) => rest.len() - remainder.len(),
+        Err(_) => 0,
+    };
+    let offset = start + consumed;
+    match input[offset..end].chars().next() {
+        Some(open) if "([{".contains(open) => {
+            match matching_bracket(&input[..end], offset) {
+                None => (end, Some(closing(open).chars().next().unwrap())),
+                Some((close, c))
+                    if c != closing(open).chars().next().unwrap() =>
+                {
+                    (close, Some(closing(open).chars().next().unwrap()))
+                }
+                Some((close, _)) => match stop_offset(input, offset + 1, close)
+                {
+                    // the bracket itself is fine, so the error follows it
+                    (inner, None) if inner == close => (close + 1, None),
+                    inner => inner,
+                },
+            }
+        }
+        // an adduct separator, the error is in the adduct or after it
+        Some(separator) if "·.*".contains(separator) => {
+            let adduct = offset + separator.len_utf8();
+            let count = input[adduct..end]
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+            stop_offset(input, adduct + count, end)
+        }
+        _ => (offset, None),
+    }
+}
+
+/// The offset and character of the bracket closing the one at `open`
+fn matching_bracket(input: &str, open: usize) -> Option<(usize, char)> {
+    let mut depth = 0;
+    for (i, c) in input[open..].char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((open + i, c));
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// The innermost bracket left open at the end of `input`
+fn open_bracket(input: &str) -> Option<char> {
+    let mut stack: Vec<char> = Vec::new();
+    for c in input.chars() {
+        match c {
+            '(' | '[' | '{' => stack.push(c),
+            ')' | ']' | '}' => {
+                stack.pop();
+            }
+            _ => (),
+        }
+    }
+    stack.pop()
+}
+
+fn closing(open: char) -> &'static str {
+    match open {
+        '[' => "]",
+        '{' => "}",
+        _ => ")",
+    }
+}
+
+/// Re-cases the run of letters around the offset into element symbols,
+/// keeping the formulas that then parse, e.g. co gives Co and CO
+fn suggestions(input: &str, offset: usize) -> Vec<String> {
+    let is_letter = |c: &char| c.is_ascii_alphabetic();
+    let run_start =
+        offset - input[..offset].chars().rev().take_while(is_letter).count();
+    let run_end =
+        offset + input[offset..].chars().take_while(is_letter).count();
+    let run = &input[run_start..run_end];
+    if run.is_empty() || run.len() > 12 {
+        return vec![];
+    }
+    symbol_spellings(&run.to_ascii_lowercase())
+        .into_iter()
+        .filter(|spelling| spelling != run)
+        .map(|spelling| {
+            format!("{}{}{}", &input[..run_start], spelling, &input[run_end..])
+        })
+        .filter(|formula| matches!(formula_parser(formula), Ok(("", _))))
+        .take(4)
+        .collect()
+}
+
+/// Every way to spell lower case letters as a sequence of element symbols
+fn symbol_spellings(letters: &str) -> Vec<String> {
+    if letters.is_empty() {
+        return vec![String::new()];
+    }
+    (1..=letters.len().min(2))
+        .rev()
+        .filter_map(|n| {
+            let mut symbol = letters[..n].to_string();
+            symbol[..1].make_ascii_uppercase();
+            element_from_string(&symbol).ok().map(|_| (symbol, n))
+        })
+        .flat_map(|(symbol, n)| {
+            symbol_spellings(&letters[n..])
+                .into_iter()
+                .map(move |rest| format!("{}{}", symbol, rest))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::parse_formula_v2;
+
+    #[test]
+    fn test_trailing_garbage() {
+        let error = parse_formula_v2("H2O$$").unwrap_err();
+        assert_eq!(error.offset, 3);
+        assert_eq!(error.token, "$");
+        let error = parse_formula_v2("NaClxyz").unwrap_err();
+        assert_eq!(error.offset, 4);
+        assert_eq!(error.token, "xyz");
+        assert!(error.suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let error = parse_formula_v2("CL").unwrap_err();
+        assert_eq!(error.offset, 1);
+        assert_eq!(error.suggestions, vec!["Cl"]);
+        let error = parse_formula_v2("co").unwrap_err();
+        assert_eq!(error.offset, 0);
+        assert_eq!(error.suggestions, vec!["Co", "CO"]);
+        let error = parse_formula_v2("CCL4").unwrap_err();
+        assert_eq!(error.suggestions, vec!["CCl4"]);
+    }
+
+    #[test]
+    fn test_brackets() {
+        let error = parse_formula_v2("H2(SO4").unwrap_err();
+        assert_eq!(error.offset, 6);
+        assert_eq!(error.token, "");
+        assert_eq!(error.expected, vec![")"]);
+        let error = parse_formula_v2("CuSO4·5Xx").unwrap_err();
+        assert_eq!(error.offset, 8);
+        assert_eq!(error.token, "Xx");
+        let error = parse_formula_v2("Ca(PXO4)2").unwrap_err();
+        assert_eq!(error.offset, 4);
+        assert_eq!(error.token, "X");
+    }
+
+    #[test]
+    fn test_display() {
+        let error = parse_formula_v2("CL").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid formula \"CL\": unexpected \"L\", expected element symbol, count, (, [, {, charge or * (did you mean Cl?) at column 2\n  CL\n   ^"
+        );
+    }
+}
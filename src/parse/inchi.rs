@@ -0,0 +1,206 @@
+use nom::combinator::all_consuming;
+use rug::Rational;
+
+use crate::model::{hill_order, Element, ElementCounts};
+use crate::parse::formula_error::FormulaError;
+use crate::parse::v2::{plain_group, Adduct, Formula};
+
+/// The start of an InChI, e.g. InChI=1S/CH4/h1H4
+pub(crate) const INCHI_PREFIX: &str = "InChI=";
+
+/// Splits a leading count off, e.g. the 2 of 2H2O, which is 1 if absent
+fn leading_count(input: &str) -> (u32, &str) {
+    let end = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    match input[..end].parse::<u32>() {
+        Ok(count) => (count, &input[end..]),
+        Err(_) => (1, input),
+    }
+}
+
+/// Parses the charge layer, one charge per molecule with repeats written
+/// like 2*+1, e.g. q;+2 or q2*+1;-2, into the net charge
+fn net_charge(layer: &str) -> Option<i32> {
+    layer[1..]
+        .split(';')
+        .map(|charge| {
+            let (repeats, charge) = match charge.split_once('*') {
+                Some((repeats, charge)) => {
+                    (repeats.parse::<i32>().ok()?, charge)
+                }
+                None => (1, charge),
+            };
+            match charge.is_empty() {
+                true => Some(0),
+                false => Some(repeats * charge.parse::<i32>().ok()?),
+            }
+        })
+        .sum()
+}
+
+/// Parse the formula, charge (/q) and protonation (/p) layers of an InChI,
+/// e.g. InChI=1S/C2H4O2.Na/c1-2(3)4;/h1H3,(H,3,4);/q;+1/p-1 for sodium
+/// acetate. Other layers are skipped. Components are joined into the base
+/// formula, written in Hill order, except that water beside another
+/// component is an adduct, as in a hydrate.
+pub fn parse_inchi(inchi: &str) -> Result<Formula, FormulaError> {
+    let error = |offset: usize, expected: &str| {
+        FormulaError::expected(inchi, offset, expected)
+    };
+    let body = inchi
+        .strip_prefix(INCHI_PREFIX)
+        .ok_or_else(|| error(0, INCHI_PREFIX))?;
+    let mut layers =
+        body.split('/').scan(INCHI_PREFIX.len(), |offset, layer| {
+            let start = *offset;
+            *offset += layer.len() + 1;
+            Some((start, layer))
+        });
+    match layers.next() {
+        Some((_, version)) if version.starts_with('1') => {}
+        _ => return Err(error(INCHI_PREFIX.len(), "InChI version 1")),
+    }
+    let mut components: Vec<(u32, ElementCounts)> = vec![];
+    let mut charge = 0;
+    let mut protons: Option<(usize, i32)> = None;
+    for (index, (offset, layer)) in layers.enumerate() {
+        match layer.chars().next() {
+            // a formula layer comes first, but a bare proton has none
+            Some(c) if index == 0 && !c.is_ascii_lowercase() => {
+                let mut start = offset;
+                for component in layer.split('.') {
+                    let (count, formula) = leading_count(component);
+                    let formula_start = start + component.len() - formula.len();
+                    let (_, atoms) = all_consuming(plain_group)(formula)
+                        .map_err(|_| error(formula_start, "Hill formula"))?;
+                    components.push((count, atoms));
+                    start += component.len() + 1;
+                }
+            }
+            Some('q') => {
+                charge = net_charge(layer)
+                    .ok_or_else(|| error(offset, "charges"))?;
+            }
+            Some('p') => {
+                let count = layer[1..]
+                    .parse::<i32>()
+                    .map_err(|_| error(offset + 1, "proton count"))?;
+                protons = Some((offset, count));
+            }
+            // the fixed hydrogen and reconnected layers repeat the others
+            Some('f') | Some('r') => break,
+            _ => {}
+        }
+    }
+    let water = |atoms: &ElementCounts| {
+        let oxygen = Element::from_symbol("O").unwrap();
+        let hydrogen = Element::from_symbol("H").unwrap();
+        atoms.len() == 2
+            && atoms.get(&oxygen) == Some(&Rational::from(1))
+            && atoms.get(&hydrogen) == Some(&Rational::from(2))
+    };
+    let hydrate = components.len() > 1;
+    let mut atoms = ElementCounts::new();
+    let mut base = ElementCounts::new();
+    let mut adducts: Vec<Adduct> = vec![];
+    for (count, component) in components {
+        let adduct = hydrate && water(&component);
+        for (element, n) in &component {
+            *atoms.entry(*element).or_default() += Rational::from(n * count);
+            if !adduct {
+                *base.entry(*element).or_default() += Rational::from(n * count);
+            }
+        }
+        if adduct {
+            adducts.push(Adduct {
+                count: Rational::from(count),
+                formula: hill_order(&component),
+                atoms: component,
+            });
+        }
+    }
+    if let Some((offset, protons)) = protons {
+        let hydrogen = Element::from_symbol("H").unwrap();
+        for counts in [&mut atoms, &mut base] {
+            let hydrogens = counts.entry(hydrogen).or_default();
+            *hydrogens += protons;
+            if *hydrogens < 0 {
+                return Err(error(offset, "protons the formula has"));
+            }
+            if *hydrogens == 0 {
+                counts.remove(&hydrogen);
+            }
+        }
+        charge += protons;
+    }
+    if atoms.is_empty() {
+        return Err(error(inchi.len(), "formula layer"));
+    }
+    Ok(Formula {
+        atoms,
+        charge,
+        base: hill_order(&base),
+        adducts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Compound;
+    use crate::parse::inchi::parse_inchi;
+
+    fn same_atoms(inchi: &str, formula: &str) {
+        let parsed = parse_inchi(inchi).unwrap();
+        let expected = Compound::from_formula(formula).unwrap();
+        assert_eq!(parsed.atoms, expected.atoms, "{}", inchi);
+        assert_eq!(parsed.charge, expected.charge, "{}", inchi);
+    }
+
+    #[test]
+    fn test_formula_layer() {
+        same_atoms("InChI=1S/CH4/h1H4", "CH4");
+        same_atoms("InChI=1S/C2H7N/c1-3-2/h3H,1-2H3", "C2H7N");
+        same_atoms(
+            "InChI=1S/C2H4O2.Na/c1-2(3)4;/h1H3,(H,3,4);/q;+1/p-1",
+            "C2H3NaO2",
+        );
+        same_atoms("InChI=1/H2O/h1H2", "H2O");
+    }
+
+    #[test]
+    fn test_charge_layers() {
+        same_atoms("InChI=1S/H3N/h1H3/p+1", "NH4+");
+        same_atoms("InChI=1S/p+1", "H+");
+        same_atoms("InChI=1S/Fe/q+3", "Fe^3+");
+        same_atoms(
+            "InChI=1S/2Na.H2O4S/c;;1-5(2,3)4/h;;(H2,1,2,3,4)/q2*+1;/p-2",
+            "Na2SO4",
+        );
+        same_atoms("InChI=1S/ClH.Ca/h1H;/q;+2/p-1", "CaCl^+");
+    }
+
+    #[test]
+    fn test_hydrate() {
+        let parsed =
+            parse_inchi("InChI=1S/Cu.H2O4S.5H2O/c;1-5(2,3)4;;;;;/h;(H2,1,2,3,4);5*1H2/q+2;/p-2")
+                .unwrap();
+        assert_eq!(parsed.base, "CuO4S");
+        assert_eq!(parsed.adducts.len(), 1);
+        assert_eq!(parsed.adducts[0].count, 5);
+        same_atoms(
+            "InChI=1S/Cu.H2O4S.5H2O/c;1-5(2,3)4;;;;;/h;(H2,1,2,3,4);5*1H2/q+2;/p-2",
+            "CuSO4·5H2O",
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |inchi| parse_inchi(inchi).unwrap_err();
+        assert_eq!(error("InChI=2/CH4").offset, 6);
+        assert_eq!(error("InChI=1S/CH4.Xx").offset, 13);
+        assert_eq!(error("InChI=1S/CH4/p-5").offset, 13);
+        assert_eq!(error("InChI=1S/CH4/qx").offset, 13);
+        assert!(parse_inchi("CH4").is_err());
+    }
+}
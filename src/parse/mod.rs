@@ -1,5 +1,20 @@
-mod v1;
+mod abbreviations;
+mod coefficient;
+mod equation;
+mod formula_error;
+mod inchi;
+mod normalize;
+mod smiles;
 mod v2;
 
-pub use v1::parse_formula;
-pub use v2::parse_formula_v2;
+pub use abbreviations::{
+    add_abbreviation, load_abbreviations, set_abbreviations_enabled,
+};
+pub use coefficient::parse_coefficient;
+pub use equation::{parse_equation, Arrow, Equation, EquationError, Term};
+pub use formula_error::FormulaError;
+pub use inchi::parse_inchi;
+pub(crate) use inchi::INCHI_PREFIX;
+pub use normalize::normalize;
+pub use smiles::parse_smiles;
+pub use v2::{parse_formula_v2, Adduct, Formula};
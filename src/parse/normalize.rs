@@ -0,0 +1,159 @@
+/// Rewrites chemistry text pasted from papers and web pages into the plain
+/// notation the parsers read, e.g. H₂O to H2O, SO₄²⁻ to SO4^2-,
+/// Fe<sup>3+</sup> to Fe^3+ and CuSO4•5H2O to CuSO4·5H2O.
+/// Works on single formulas and on full equations.
+pub fn normalize(input: &str) -> String {
+    let decoded = decode_entities(input);
+    let mut normalized = String::with_capacity(decoded.len());
+    let mut in_superscript = false;
+    let mut rest = decoded.as_str();
+    while let Some(c) = rest.chars().next() {
+        if let Some((tag, after)) = html_tag(rest) {
+            // <sup>2-</sup> is a charge, <sub>2</sub> is just a count
+            if tag == "sup" && !normalized.ends_with('^') {
+                normalized.push('^');
+            }
+            rest = after;
+            continue;
+        }
+        match superscript(c) {
+            Some(plain) => {
+                if !in_superscript && !normalized.ends_with('^') {
+                    normalized.push('^');
+                }
+                normalized.push(plain);
+                in_superscript = true;
+            }
+            None => {
+                normalized.push(plain(c));
+                in_superscript = false;
+            }
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    normalized
+}
+
+/// The plain character for a superscript digit or sign
+fn superscript(c: char) -> Option<char> {
+    match c {
+        '⁰' => Some('0'),
+        '¹' => Some('1'),
+        '²' => Some('2'),
+        '³' => Some('3'),
+        '⁴'..='⁹' => char::from_digit(c as u32 - '⁰' as u32, 10),
+        '⁺' => Some('+'),
+        '⁻' => Some('-'),
+        _ => None,
+    }
+}
+
+/// The plain character for subscripts, dots, signs, spaces and arrows
+fn plain(c: char) -> char {
+    match c {
+        '₀'..='₉' => char::from_digit(c as u32 - '₀' as u32, 10).unwrap(),
+        '•' | '∙' | '⋅' | '・' => '·',
+        '−' | '₋' => '-',
+        '＋' | '₊' => '+',
+        '⟶' | '⟹' | '➔' | '➝' => '→',
+        '⇄' | '⇆' | '⥂' => '⇌',
+        '\u{a0}' | '\u{2009}' | '\u{202f}' => ' ',
+        c => c,
+    }
+}
+
+/// An HTML tag at the start of the input, its lower case name without
+/// attributes, e.g. sup or /sup, and the input after it.
+/// Arrows like <=> are not tags.
+fn html_tag(input: &str) -> Option<(String, &str)> {
+    let inner = input.strip_prefix('<')?;
+    let name = inner.strip_prefix('/').unwrap_or(inner);
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let end = inner.find('>')?;
+    let tag: String = inner
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '/')
+        .collect();
+    Some((tag.to_ascii_lowercase(), &inner[end + 1..]))
+}
+
+/// Replaces the HTML entities found in chemistry text, e.g. &middot; and
+/// &#8594;, leaving any other ampersand as it is
+fn decode_entities(input: &str) -> String {
+    let mut decoded = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let entity = &rest[start + 1..];
+        let end = entity.find(';').filter(|&end| end <= 8);
+        match end.and_then(|end| entity_char(&entity[..end])) {
+            Some(c) => {
+                decoded.push(c);
+                rest = &entity[end.unwrap() + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = entity;
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn entity_char(name: &str) -> Option<char> {
+    match name {
+        "middot" | "sdot" | "bull" => Some('·'),
+        "rarr" | "rightarrow" => Some('→'),
+        "rlhar" | "rightleftharpoons" => Some('⇌'),
+        "minus" => Some('-'),
+        "plus" => Some('+'),
+        "nbsp" | "thinsp" => Some(' '),
+        _ => match name.strip_prefix('#') {
+            Some(hex) if hex.starts_with(['x', 'X']) => {
+                u32::from_str_radix(&hex[1..], 16).ok()?.try_into().ok()
+            }
+            Some(decimal) => decimal.parse::<u32>().ok()?.try_into().ok(),
+            None => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::normalize;
+
+    #[test]
+    fn test_unicode() {
+        assert_eq!(normalize("H₂O"), "H2O");
+        assert_eq!(normalize("SO₄²⁻"), "SO4^2-");
+        assert_eq!(normalize("Fe³⁺"), "Fe^3+");
+        assert_eq!(normalize("NH₄⁺"), "NH4^+");
+        assert_eq!(normalize("SO4^2−"), "SO4^2-");
+        assert_eq!(normalize("CuSO₄•5H₂O"), "CuSO4·5H2O");
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(normalize("H<sub>2</sub>O"), "H2O");
+        assert_eq!(normalize("Fe<SUP>3+</SUP>"), "Fe^3+");
+        assert_eq!(normalize("CuSO<sub>4</sub>&middot;5H2O"), "CuSO4·5H2O");
+        assert_eq!(normalize("<i>e</i><sup>&minus;</sup>"), "e^-");
+        assert_eq!(normalize("H2 &#8594; H2"), "H2 → H2");
+        assert_eq!(
+            normalize("H2 + O2 <=> H2O & more"),
+            "H2 + O2 <=> H2O & more"
+        );
+    }
+
+    #[test]
+    fn test_equation() {
+        assert_eq!(normalize("2H₂ + O₂ ⟶ 2H₂O"), "2H2 + O2 → 2H2O");
+        assert_eq!(
+            normalize("MnO₄⁻ + 8H⁺ + 5e⁻ → Mn²⁺ + 4H₂O"),
+            "MnO4^- + 8H^+ + 5e^- → Mn^2+ + 4H2O"
+        );
+    }
+}
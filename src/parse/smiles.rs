@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use crate::model::{hill_order, Element, ElementCounts};
+use crate::parse::formula_error::FormulaError;
+use crate::parse::v2::{element_from_string, Formula};
+
+/// The prefix marking a species written as SMILES, e.g. smiles:CC(=O)O
+pub(crate) const SMILES_PREFIX: &str = "smiles:";
+
+/// Atoms that may be written without brackets, two letter symbols first
+const ORGANIC: &[&str] = &["Cl", "Br", "B", "C", "N", "O", "P", "S", "F", "I"];
+
+/// Aromatic atoms, of which only b, c, n, o, p and s may be written without
+/// brackets
+const AROMATIC: &[&str] = &["se", "as", "te", "b", "c", "n", "o", "p", "s"];
+
+/// An atom read from SMILES, with the bonds made to it so far
+struct Atom {
+    element: Element,
+    aromatic: bool,
+    /// The hydrogens of a bracket atom, or None when they are implicit
+    hydrogens: Option<u32>,
+    charge: i32,
+    /// The sum of the orders of its bonds, where aromatic bonds count one
+    bonds: u32,
+}
+
+impl Atom {
+    /// The hydrogens written in brackets, or else enough to reach the lowest
+    /// normal valence its bonds allow. An aromatic atom also has a share of
+    /// the ring's double bonds, so it takes one less.
+    fn hydrogen_count(&self) -> u32 {
+        if let Some(hydrogens) = self.hydrogens {
+            return hydrogens;
+        }
+        let valences: &[u32] = match self.element.get_symbol() {
+            "B" => &[3],
+            "C" => &[4],
+            "N" | "P" => &[3, 5],
+            "O" => &[2],
+            "S" => &[2, 4, 6],
+            _ => &[1],
+        };
+        match self.aromatic {
+            true => valences[0].saturating_sub(self.bonds + 1),
+            false => valences
+                .iter()
+                .find(|&&valence| valence >= self.bonds)
+                .map_or(0, |valence| valence - self.bonds),
+        }
+    }
+}
+
+/// Where an atom could not be read, and what was expected there
+type AtomError<'a> = (&'a str, &'static str);
+
+/// Reads leading digits, e.g. the 13 in 13CH4
+fn digits(input: &str) -> (&str, Option<u32>) {
+    let end = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    match input[..end].parse::<u32>() {
+        Ok(n) => (&input[end..], Some(n)),
+        Err(_) => (input, None),
+    }
+}
+
+/// Reads the first of `symbols` that the input starts with
+fn symbol<'a>(input: &'a str, symbols: &[&str]) -> Option<(&'a str, &'a str)> {
+    symbols
+        .iter()
+        .find(|symbol| input.starts_with(*symbol))
+        .map(|symbol| input.split_at(symbol.len()))
+        .map(|(symbol, rest)| (rest, symbol))
+}
+
+/// Looks up an aromatic symbol, e.g. c for carbon
+fn aromatic_element(symbol: &str) -> Option<Element> {
+    let mut chars = symbol.chars();
+    let first = chars.next()?.to_ascii_uppercase();
+    element_from_string(&format!("{}{}", first, chars.as_str())).ok()
+}
+
+/// Parses an atom of the organic subset, e.g. C, Cl or aromatic c, whose
+/// hydrogens are implicit
+fn organic_atom(input: &str) -> Option<(&str, Atom)> {
+    let atom = |element, aromatic| Atom {
+        element,
+        aromatic,
+        hydrogens: None,
+        charge: 0,
+        bonds: 0,
+    };
+    if let Some((rest, symbol)) = symbol(input, ORGANIC) {
+        let element = element_from_string(symbol).ok()?;
+        return Some((rest, atom(element, false)));
+    }
+    let (rest, symbol) = symbol(input, &AROMATIC[3..])?;
+    Some((rest, atom(aromatic_element(symbol)?, true)))
+}
+
+/// Skips a chirality mark, e.g. @, @@ or @TH1
+fn chirality(input: &str) -> &str {
+    let rest = input.strip_prefix('@').unwrap_or(input);
+    let rest = rest.strip_prefix('@').unwrap_or(rest);
+    match ["TH", "AL", "SP", "TB", "OH"]
+        .iter()
+        .find_map(|class| rest.strip_prefix(class))
+    {
+        Some(after) if rest.len() < input.len() => digits(after).0,
+        _ => rest,
+    }
+}
+
+/// Parses a bracket atom charge, e.g. +, -, ++ or +2
+fn charge(input: &str) -> (&str, i32) {
+    let sign = match input.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return (input, 0),
+    };
+    let rest = &input[1..];
+    match digits(rest) {
+        (after, Some(n)) => (after, sign * n as i32),
+        (_, None) => {
+            let repeats =
+                rest.len() - rest.trim_start_matches(&input[..1]).len();
+            (&rest[repeats..], sign * (repeats as i32 + 1))
+        }
+    }
+}
+
+/// Parses a bracket atom, e.g. [NH4+], [13CH4], [nH] or [C@@H]
+fn bracket_atom(input: &str) -> Result<(&str, Atom), AtomError<'_>> {
+    let rest = &input[1..];
+    let (rest, mass_number) = digits(rest);
+    let (after, (element, aromatic)) = match symbol(rest, AROMATIC) {
+        Some((after, symbol)) => (after, (aromatic_element(symbol), true)),
+        None => {
+            let upper = rest.starts_with(|c: char| c.is_ascii_uppercase());
+            let two = rest.get(..2).filter(|s| {
+                upper && s.ends_with(|c: char| c.is_ascii_lowercase())
+            });
+            match two.and_then(|two| element_from_string(two).ok()) {
+                Some(element) => (&rest[2..], (Some(element), false)),
+                None => match rest.get(..1).filter(|_| upper) {
+                    Some(one) => {
+                        (&rest[1..], (element_from_string(one).ok(), false))
+                    }
+                    None => (rest, (None, false)),
+                },
+            }
+        }
+    };
+    let element = element.ok_or((rest, "element symbol"))?;
+    let element = match mass_number {
+        Some(mass_number) => u16::try_from(mass_number)
+            .ok()
+            .and_then(|mass_number| element.with_mass_number(mass_number))
+            .ok_or((&input[1..], "known isotope"))?,
+        None => element,
+    };
+    let rest = chirality(after);
+    let (rest, hydrogens) = match rest.strip_prefix('H') {
+        Some(after) => match digits(after) {
+            (after, Some(n)) => (after, n),
+            (after, None) => (after, 1),
+        },
+        None => (rest, 0),
+    };
+    let (rest, charge) = charge(rest);
+    let rest = match rest.strip_prefix(':') {
+        Some(after) => match digits(after) {
+            (after, Some(_)) => after,
+            (_, None) => return Err((after, "atom class")),
+        },
+        None => rest,
+    };
+    let rest = rest.strip_prefix(']').ok_or((rest, "]"))?;
+    Ok((
+        rest,
+        Atom {
+            element,
+            aromatic,
+            hydrogens: Some(hydrogens),
+            charge,
+            bonds: 0,
+        },
+    ))
+}
+
+/// Parse a SMILES string into its atoms and net charge, adding the
+/// implicit hydrogens. The base formula is written in Hill order.
+/// # Examples
+/// parse_smiles("CC(=O)O")
+/// parse_smiles("c1ccncc1")
+/// parse_smiles("[NH4+].[Cl-]")
+pub fn parse_smiles(smiles: &str) -> Result<Formula, FormulaError> {
+    let error = |rest: &str, expected: &str| {
+        FormulaError::expected(smiles, smiles.len() - rest.len(), expected)
+    };
+    let mut atoms: Vec<Atom> = vec![];
+    let mut previous: Option<usize> = None;
+    let mut branches: Vec<Option<usize>> = vec![];
+    // ring number to the atom that opened it and the bond written there
+    let mut rings: HashMap<u32, (usize, Option<u32>)> = HashMap::new();
+    let mut bond: Option<u32> = None;
+    let mut rest = smiles;
+    while let Some(c) = rest.chars().next() {
+        let order = match c {
+            '-' | '/' | '\\' | ':' => Some(1),
+            '=' => Some(2),
+            '#' => Some(3),
+            '$' => Some(4),
+            _ => None,
+        };
+        let structural = order.is_some() || "().".contains(c);
+        if structural && (bond.is_some() || (c != ')' && previous.is_none())) {
+            return Err(error(rest, "atom"));
+        }
+        match c {
+            _ if order.is_some() => bond = order,
+            '(' => branches.push(previous),
+            ')' => {
+                previous = branches.pop().ok_or_else(|| error(rest, "atom"))?
+            }
+            '.' => previous = None,
+            '0'..='9' | '%' => {
+                let (after, number) = match c {
+                    '%' => match rest.get(1..3).map(|n| n.parse::<u32>()) {
+                        Some(Ok(number)) => (&rest[3..], number),
+                        _ => return Err(error(&rest[1..], "ring number")),
+                    },
+                    _ => (&rest[1..], c.to_digit(10).unwrap()),
+                };
+                let atom = previous.ok_or_else(|| error(rest, "atom"))?;
+                match rings.remove(&number) {
+                    Some((other, other_bond)) => {
+                        let order = bond.or(other_bond).unwrap_or(1);
+                        atoms[atom].bonds += order;
+                        atoms[other].bonds += order;
+                    }
+                    None => {
+                        rings.insert(number, (atom, bond));
+                    }
+                }
+                bond = None;
+                rest = after;
+                continue;
+            }
+            _ => {
+                let (after, atom) = match c {
+                    '[' => bracket_atom(rest)
+                        .map_err(|(at, expected)| error(at, expected))?,
+                    _ => {
+                        organic_atom(rest).ok_or_else(|| error(rest, "atom"))?
+                    }
+                };
+                atoms.push(atom);
+                let index = atoms.len() - 1;
+                if let Some(previous) = previous {
+                    let order = bond.take().unwrap_or(1);
+                    atoms[previous].bonds += order;
+                    atoms[index].bonds += order;
+                }
+                previous = Some(index);
+                rest = after;
+                continue;
+            }
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    if atoms.is_empty() || bond.is_some() {
+        return Err(error(rest, "atom"));
+    }
+    if !branches.is_empty() {
+        return Err(error(rest, ")"));
+    }
+    if let Some(number) = rings.keys().min() {
+        return Err(error(rest, &format!("ring closure {}", number)));
+    }
+    let hydrogen = Element::from_symbol("H").unwrap();
+    let mut counts = ElementCounts::new();
+    for atom in &atoms {
+        *counts.entry(atom.element).or_default() += 1;
+        let hydrogens = atom.hydrogen_count();
+        if hydrogens > 0 {
+            *counts.entry(hydrogen).or_default() += hydrogens;
+        }
+    }
+    Ok(Formula {
+        base: hill_order(&counts),
+        atoms: counts,
+        charge: atoms.iter().map(|atom| atom.charge).sum(),
+        adducts: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Compound;
+    use crate::parse::smiles::parse_smiles;
+
+    fn same_atoms(smiles: &str, formula: &str) {
+        let parsed = parse_smiles(smiles).unwrap();
+        let expected = Compound::from_formula(formula).unwrap();
+        assert_eq!(parsed.atoms, expected.atoms, "{}", smiles);
+        assert_eq!(parsed.charge, expected.charge, "{}", smiles);
+    }
+
+    #[test]
+    fn test_organic_subset() {
+        same_atoms("C", "CH4");
+        same_atoms("CC(=O)O", "C2H4O2");
+        same_atoms("C#N", "HCN");
+        same_atoms("ClC(Cl)Cl", "CHCl3");
+        same_atoms("CS(=O)C", "C2H6OS");
+        same_atoms("OP(=O)(O)O", "H3PO4");
+        same_atoms("C/C=C/C", "C4H8");
+        assert_eq!(parse_smiles("CC(=O)O").unwrap().base, "C2H4O2");
+    }
+
+    #[test]
+    fn test_rings_and_aromatics() {
+        same_atoms("C1CCCCC1", "C6H12");
+        same_atoms("c1ccccc1", "C6H6");
+        same_atoms("c1ccncc1", "C5H5N");
+        same_atoms("c1cc[nH]c1", "C4H5N");
+        same_atoms("c1ccsc1", "C4H4S");
+        same_atoms("c1ccc2ccccc2c1", "C10H8");
+        same_atoms("C%10CC%10", "C3H6");
+        same_atoms("CC(=O)Oc1ccccc1C(=O)O", "C9H8O4");
+    }
+
+    #[test]
+    fn test_bracket_atoms() {
+        same_atoms("[NH4+]", "NH4+");
+        same_atoms("[NH4+].[Cl-]", "NH4Cl");
+        same_atoms("[O-]S(=O)(=O)[O-]", "SO4^2-");
+        same_atoms("[Fe+3]", "Fe^3+");
+        same_atoms("[Cu++]", "Cu^2+");
+        same_atoms("[13CH4]", "[13C]H4");
+        same_atoms("[2H]O[2H]", "D2O");
+        same_atoms("N[C@@H](C)C(=O)O", "C3H7NO2");
+        assert_eq!(parse_smiles("[13CH3]I").unwrap().base, "[13C]H3I");
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |smiles| parse_smiles(smiles).unwrap_err();
+        assert_eq!(
+            error("C1CC").describe(),
+            "unexpected end of formula, expected ring closure 1"
+        );
+        assert_eq!(
+            error("CC(C").describe(),
+            "unexpected end of formula, expected )"
+        );
+        assert_eq!(error("C[Xx]").offset, 2);
+        assert_eq!(
+            error("C[CH4").describe(),
+            "unexpected end of formula, expected ]"
+        );
+        assert_eq!(error("C==C").offset, 2);
+        assert_eq!(error("Cx").token, "x");
+        assert!(parse_smiles("").is_err());
+        assert!(parse_smiles("[99C]").is_err());
+    }
+}
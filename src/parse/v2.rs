@@ -1,210 +1,633 @@
 use std::collections::HashMap;
+use std::panic;
 
 use nom::branch::alt;
-use nom::bytes::complete::take_while1;
-use nom::character::complete::{digit1, one_of};
-use nom::combinator::map;
+use nom::character::complete::{char, digit1, one_of, satisfy};
+use nom::combinator::{consumed, map, not, peek, recognize, value};
 use nom::{
-    bytes::complete::{tag, take_while_m_n},
     combinator::{map_res, opt},
-    multi::many1,
-    sequence::delimited,
+    multi::{many0, many1},
     sequence::pair,
+    sequence::{delimited, preceded, terminated, tuple},
     IResult, Parser,
 };
 
-use crate::model::Element;
-use crate::parse::v1::element_from_string;
+use rug::Rational;
+
+use crate::model::{Element, ElementCounts};
+use crate::parse::abbreviations::abbreviation;
+use crate::parse::coefficient::{decimal, integer};
+use crate::parse::formula_error::FormulaError;
+use crate::parse::inchi::{parse_inchi, INCHI_PREFIX};
+use crate::parse::normalize::normalize;
+use crate::parse::smiles::{parse_smiles, SMILES_PREFIX};
+
+/// The atoms and net charge parsed from a formula
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Formula {
+    /// All atoms, adducts included
+    pub atoms: ElementCounts,
+    pub charge: i32,
+    /// The formula before any adduct, e.g. CuSO4 in CuSO4·5H2O
+    pub base: String,
+    pub adducts: Vec<Adduct>,
+}
+
+/// A molecule joined to a formula, e.g. the 5H2O in CuSO4·5H2O
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Adduct {
+    pub count: Rational,
+    /// The formula of one molecule, e.g. H2O
+    pub formula: String,
+    pub atoms: ElementCounts,
+}
+
+/// Looks up an element by its exact symbol, e.g. Na but not NA
+pub(crate) fn element_from_string(symbol: &str) -> Result<Element, String> {
+    match symbol.chars().all(|c| c.is_ascii_alphabetic()) {
+        true => {
+            let e: Result<Option<Element>, _> =
+                panic::catch_unwind(|| Element::from_symbol(symbol));
+            e.unwrap_or(None)
+                .ok_or(format!("Invalid symbol {}", symbol))
+        }
+        false => Err(format!("Invalid symbol {}", symbol)),
+    }
+}
 
 /// Parse an elemental symbol, e.g. H, He, Na, S, Co
+/// A two letter symbol is preferred, so Co is cobalt, CO is carbon monoxide
+/// and Clx stops before the x
 fn symbol(sym: &str) -> IResult<&str, Element> {
+    let upper = |c: char| c.is_ascii_uppercase();
+    let lower = |c: char| c.is_ascii_lowercase();
+    alt((
+        map_res(recognize(pair(satisfy(upper), satisfy(lower))), |s| {
+            element_from_string(s)
+        }),
+        map_res(recognize(satisfy(upper)), element_from_string),
+    ))(sym)
+}
+
+/// Parses an isotope label, e.g. [13C], or ^13C from a superscript ¹³C.
+/// D and T are read as symbols.
+fn isotope(isotope: &str) -> IResult<&str, Element> {
+    let mass_number = |s| map_res(digit1, |n: &str| n.parse::<u16>())(s);
     map_res(
-        map(
-            // one capital letter and possibly a second lower-case letter
-            pair(
-                take_while_m_n(1, 1, |c: char| c.is_ascii_uppercase()),
-                opt(take_while1(|c: char| c.is_ascii_lowercase())),
-            ),
-            |(one, two): (&str, Option<&str>)| {
-                format!("{}{}", one, two.unwrap_or(""))
-            },
-        ),
-        |s| element_from_string(&s),
-    )(sym)
+        alt((
+            delimited(char('['), pair(mass_number, symbol), char(']')),
+            preceded(char('^'), pair(mass_number, symbol)),
+        )),
+        |(mass_number, element)| {
+            element.with_mass_number(mass_number).ok_or_else(|| {
+                format!("Unknown isotope {}{}", mass_number, element)
+            })
+        },
+    )(isotope)
 }
 
-/// Parse the multiplier following a symbol, must be an integer (1 or more digits)
-fn multiplier(mult: &str) -> IResult<&str, u64> {
-    map_res(digit1, |s: &str| s.parse::<u64>())(mult)
+/// Parse the multiplier following a symbol or bracket, an integer or a
+/// decimal, e.g. 2 or 0.95 in Fe0.95O. A . between digits is always a
+/// decimal point, so Li2.5Mn2O4 has 2.5 Li and an adduct after a count
+/// must be written with · or *, as in CuSO4·5H2O.
+fn multiplier(mult: &str) -> IResult<&str, Rational> {
+    alt((decimal, integer))(mult)
 }
 
 /// Parse an element with its multiplier
-fn element(elem: &str) -> IResult<&str, (Element, u64)> {
+fn element(elem: &str) -> IResult<&str, (Element, Rational)> {
     // no multiplier means 1
-    pair(symbol, opt(multiplier).map(|m| m.unwrap_or(1)))(elem)
+    pair(
+        alt((isotope, symbol)),
+        opt(multiplier).map(|m| m.unwrap_or_else(|| Rational::from(1))),
+    )(elem)
 }
 
 /// Sums a vector of elements and their multipliers,
 /// rolling them into a map
-fn sum_elements(vec: Vec<(Element, u64)>) -> HashMap<Element, u64> {
+fn sum_elements(vec: Vec<(Element, Rational)>) -> ElementCounts {
     vec.into_iter()
         .fold(HashMap::new(), |mut acc, (element, num)| {
-            let entry: &mut u64 = acc.entry(element.to_owned()).or_insert(0);
+            let entry = acc.entry(element).or_insert_with(Rational::new);
             *entry += num;
             acc
         })
 }
 
-/// Parses a group, which is a sequence of elements, each optionally
-/// followed by a multiplier, e.g. COOH or CO2H or NaCl2
-fn group(group: &str) -> IResult<&str, HashMap<Element, u64>> {
+/// Parses a group of elements only, each optionally followed by a
+/// multiplier, e.g. COOH or CO2H or NaCl2
+pub(crate) fn plain_group(group: &str) -> IResult<&str, ElementCounts> {
     map(many1(element), sum_elements)(group)
 }
 
-/// Parses a multi-group, which is a parenthetical group
-/// optionally followed by a multiplier, e.g. (SO4) or (SO4)2
-fn multi_group(multi_group: &str) -> IResult<&str, HashMap<Element, u64>> {
+/// Parses an abbreviation with its multiplier, e.g. Et3 or (OAc)2's OAc
+fn abbreviated(abbreviated: &str) -> IResult<&str, Vec<(Element, Rational)>> {
+    map(
+        pair(
+            abbreviation,
+            opt(multiplier).map(|m| m.unwrap_or_else(|| Rational::from(1))),
+        ),
+        |(atoms, multiplier)| {
+            atoms
+                .into_iter()
+                .map(|(el, n)| (el, n * &multiplier))
+                .collect()
+        },
+    )(abbreviated)
+}
+
+/// Parses a group, which is a sequence of elements and abbreviations, each
+/// optionally followed by a multiplier, e.g. COOH or CO2H or PhCOOH.
+/// An abbreviation is tried first, so OAc is acetate rather than O and Ac
+fn group(group: &str) -> IResult<&str, ElementCounts> {
+    map(
+        many1(alt((abbreviated, map(element, |element| vec![element])))),
+        |elements| sum_elements(elements.into_iter().flatten().collect()),
+    )(group)
+}
+
+/// Parses the contents of a bracket, any sequence of groups and nested
+/// multi-groups, e.g. Fe(CN)6 in K4[Fe(CN)6]
+fn groups(groups: &str) -> IResult<&str, ElementCounts> {
+    map(many1(alt((multi_group, group))), sum_groups)(groups)
+}
+
+/// Parses a multi-group, which is a bracketed sequence of groups
+/// optionally followed by a multiplier, e.g. (SO4) or (SO4)2 or [Fe(CN)6]
+/// The closing bracket must match the opening one
+fn multi_group(multi_group: &str) -> IResult<&str, ElementCounts> {
     map(
         pair(
-            delimited(one_of("([{"), group, one_of(")]}")),
-            opt(multiplier).map(|m| m.unwrap_or(1)),
+            alt((
+                delimited(char('('), groups, char(')')),
+                delimited(char('['), groups, char(']')),
+                delimited(char('{'), groups, char('}')),
+            )),
+            opt(multiplier).map(|m| m.unwrap_or_else(|| Rational::from(1))),
         ),
         |(group, multiplier)| {
-            group.iter().fold(HashMap::new(), |mut acc, (el, num)| {
-                let entry: &mut u64 = acc.entry(el.to_owned()).or_insert(0);
-                *entry += num * multiplier;
-                acc
-            })
+            group
+                .into_iter()
+                .map(|(el, num)| (el, num * &multiplier))
+                .collect()
         },
     )(multi_group)
 }
 
-/// Parses an asterisk followed by "H2O"
-fn hydrate(hydrate: &str) -> IResult<&str, HashMap<Element, u64>> {
-    let (hydrate, _) = tag("*")(hydrate)?;
+/// Parses an adduct after a separator, ·, . or *, with an optional count,
+/// e.g. the ·5H2O in CuSO4·5H2O, the .NH3 in BF3.NH3 or the ·0.5H2O in
+/// CaSO4·0.5H2O
+fn adduct(adduct: &str) -> IResult<&str, Adduct> {
     map(
-        pair(opt(multiplier).map(|m| m.unwrap_or(1)), tag("H2O")),
-        |(mx, _hydrate)| {
-            let mut map = HashMap::new();
-            map.insert(Element::from_symbol("H").unwrap(), 2 * mx);
-            map.insert(Element::from_symbol("O").unwrap(), mx);
-            map
+        preceded(
+            one_of("·.*"),
+            pair(
+                opt(alt((decimal, integer)))
+                    .map(|m| m.unwrap_or_else(|| Rational::from(1))),
+                consumed(groups),
+            ),
+        ),
+        |(count, (formula, atoms))| Adduct {
+            count,
+            formula: formula.to_string(),
+            atoms,
         },
-    )(hydrate)
+    )(adduct)
 }
 
 /// Sums a vector of group results
-fn sum_groups(vec: Vec<HashMap<Element, u64>>) -> HashMap<Element, u64> {
+fn sum_groups(vec: Vec<ElementCounts>) -> ElementCounts {
     vec.into_iter().fold(HashMap::new(), |mut acc, m| {
         for (el, mul) in m {
-            let c = acc.entry(el).or_insert(0);
+            let c = acc.entry(el).or_insert_with(Rational::new);
             *c += mul;
         }
         acc
     })
 }
 
-/// Parses the full formula
+/// Parses a charge sign, + or -
+fn sign(sign: &str) -> IResult<&str, i32> {
+    alt((value(1, char('+')), value(-1, char('-'))))(sign)
+}
+
+/// Parses the magnitude of a charge, must be an integer
+fn charge_magnitude(magnitude: &str) -> IResult<&str, i32> {
+    map_res(digit1, |s: &str| s.parse::<i32>())(magnitude)
+}
+
+/// Parses a net charge suffix, either after a caret, e.g. ^2- or ^-2 or ^+,
+/// or as a sign followed by the magnitude, e.g. +3 or -2,
+/// or as repeated signs, e.g. + or --
+/// A lone element with a count and a bare sign, e.g. Fe3+, is rejected as
+/// ambiguous by `parse_formula_v2`, write Fe+3 or Fe^3+ for iron(III)
+fn charge(charge: &str) -> IResult<&str, i32> {
+    alt((
+        preceded(
+            char('^'),
+            alt((
+                map(pair(charge_magnitude, sign), |(n, s)| n * s),
+                map(pair(sign, opt(charge_magnitude)), |(s, n)| {
+                    s * n.unwrap_or(1)
+                }),
+            )),
+        ),
+        map(pair(sign, charge_magnitude), |(s, n)| s * n),
+        terminated(
+            alt((
+                map(many1(char('+')), |signs| signs.len() as i32),
+                map(many1(char('-')), |signs| -(signs.len() as i32)),
+            )),
+            // in an equation, H2+O2 is a sum and H2O->H2O2 has an arrow
+            not(peek(satisfy(|c| c.is_alphanumeric() || "([{>".contains(c)))),
+        ),
+    ))(charge)
+}
+
+/// Parses an electron, e.g. e- or e^-
+fn electron(electron: &str) -> IResult<&str, Formula> {
+    value(
+        Formula {
+            atoms: HashMap::new(),
+            charge: -1,
+            base: "e-".to_string(),
+            adducts: vec![],
+        },
+        tuple((char('e'), opt(char('^')), char('-'))),
+    )(electron)
+}
+
+/// Parses a compound formula
 /// Must contain at least one group or multi-group, e.g. H2O or (SO4)2
-/// May also have a "hydrate" suffix, e.g. *6H2O
-fn formula_parser(formula: &str) -> IResult<&str, HashMap<Element, u64>> {
+/// May be followed by any number of adducts, e.g. ·5H2O or .2NH3.H2O
+/// May end with a net charge, e.g. SO4^2- or NH4+
+fn compound(formula: &str) -> IResult<&str, Formula> {
     map(
-        map(
-            pair(many1(alt((multi_group, group))), opt(hydrate)),
-            |(mut groups, maybe_hydrate)| match maybe_hydrate {
-                None => groups,
-                Some(hydrate) => {
-                    // let mut hydrate_group = ;
-                    groups.push(hydrate);
-                    groups
-                }
-            },
-        ),
-        sum_groups,
+        tuple((consumed(groups), many0(adduct), opt(charge))),
+        |((base, base_atoms), adducts, maybe_charge)| {
+            let mut groups = vec![base_atoms];
+            groups.extend(adducts.iter().map(|adduct| {
+                let count = &adduct.count;
+                adduct
+                    .atoms
+                    .iter()
+                    .map(|(el, n)| (*el, Rational::from(n * count)))
+                    .collect()
+            }));
+            Formula {
+                atoms: sum_groups(groups),
+                charge: maybe_charge.unwrap_or(0),
+                base: base.to_string(),
+                adducts,
+            }
+        },
     )(formula)
 }
 
-/// Parse a compound formula string.
+/// Parses the full formula, either an electron or a compound
+pub(crate) fn formula_parser(formula: &str) -> IResult<&str, Formula> {
+    alt((electron, compound))(formula)
+}
+
+/// Parse a compound formula string, which must be consumed entirely.
+/// Unicode and HTML notation is normalized first, see `normalize`.
 /// # Examples
 /// parse_formula_v2("H2O")
 /// parse_formula_v2("H2(SO4)2")
-pub fn parse_formula_v2(
-    formula: &str,
-) -> Result<HashMap<Element, u64>, String> {
-    let (_remainder, elems) = formula_parser(formula)
-        .map_err(|_| format!("failed to parse: {:?}", formula))?;
-    Ok(elems)
+/// parse_formula_v2("SO4^2-")
+pub fn parse_formula_v2(formula: &str) -> Result<Formula, FormulaError> {
+    if formula.starts_with(INCHI_PREFIX) {
+        return parse_inchi(formula);
+    }
+    if let Some(smiles) = formula.strip_prefix(SMILES_PREFIX) {
+        return parse_smiles(smiles).map_err(|e| FormulaError {
+            input: formula.to_string(),
+            offset: SMILES_PREFIX.len() + e.offset,
+            ..e
+        });
+    }
+    let formula = normalize(formula);
+    if let Some(error) = ambiguous_charge(&formula) {
+        return Err(error);
+    }
+    match formula_parser(&formula) {
+        Ok(("", parsed)) => Ok(parsed),
+        _ => Err(FormulaError::locate(&formula)),
+    }
+}
+
+/// An element with a count and a bare sign, e.g. Fe3+, could be the ion
+/// Fe^3+ or a cluster Fe3^+, so either must be written out
+fn ambiguous_charge(formula: &str) -> Option<FormulaError> {
+    let sign = formula.len().checked_sub(1)?;
+    let body = formula.strip_suffix(['+', '-'])?;
+    let digits = body.find(|c: char| c.is_ascii_digit())?;
+    let (symbol, count) = body.split_at(digits);
+    if !count.chars().all(|c| c.is_ascii_digit())
+        || element_from_string(symbol).is_err()
+    {
+        return None;
+    }
+    let bare = &formula[sign..];
+    let mut error =
+        FormulaError::expected(formula, sign, "^ before the charge");
+    error.suggestions = vec![
+        format!("{}^{}{}", symbol, count, bare),
+        format!("{}{}^{}", symbol, count, bare),
+    ];
+    Some(error)
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use crate::model::Element;
+    use rug::Rational;
+
+    use crate::model::{Element, ElementCounts};
     use crate::parse::v2::parse_formula_v2;
+    use crate::test_utils::parse_elements;
+
+    fn counts(formula: &str) -> ElementCounts {
+        parse_formula_v2(formula).unwrap().atoms
+    }
 
     #[test]
     fn test_h2o() {
         let formula = "H2O";
-        let map = parse_formula_v2(formula).unwrap();
+        let map = parse_formula_v2(formula).unwrap().atoms;
         let mut exp = HashMap::new();
-        exp.insert(Element::from_symbol("H").unwrap(), 2);
-        exp.insert(Element::from_symbol("O").unwrap(), 1);
+        exp.insert(Element::from_symbol("H").unwrap(), Rational::from(2));
+        exp.insert(Element::from_symbol("O").unwrap(), Rational::from(1));
         assert_eq!(map, exp);
     }
 
     #[test]
     fn test_multi_group() {
         let formula = "H2(SO4)2";
-        let map = parse_formula_v2(formula).unwrap();
+        let map = parse_formula_v2(formula).unwrap().atoms;
         let mut exp = HashMap::new();
-        exp.insert(Element::from_symbol("H").unwrap(), 2);
-        exp.insert(Element::from_symbol("S").unwrap(), 2);
-        exp.insert(Element::from_symbol("O").unwrap(), 8);
+        exp.insert(Element::from_symbol("H").unwrap(), Rational::from(2));
+        exp.insert(Element::from_symbol("S").unwrap(), Rational::from(2));
+        exp.insert(Element::from_symbol("O").unwrap(), Rational::from(8));
         assert_eq!(map, exp);
     }
 
     #[test]
     fn test_hydrate() {
         let formula = "H2(SO4)2*6H2O";
-        let map = parse_formula_v2(formula).unwrap();
+        let map = parse_formula_v2(formula).unwrap().atoms;
         let mut exp = HashMap::new();
-        exp.insert(Element::from_symbol("H").unwrap(), 14);
-        exp.insert(Element::from_symbol("S").unwrap(), 2);
-        exp.insert(Element::from_symbol("O").unwrap(), 14);
+        exp.insert(Element::from_symbol("H").unwrap(), Rational::from(14));
+        exp.insert(Element::from_symbol("S").unwrap(), Rational::from(2));
+        exp.insert(Element::from_symbol("O").unwrap(), Rational::from(14));
         assert_eq!(map, exp);
     }
 
     #[test]
     fn test_organic() {
         let formula = "C6H5COOH";
-        let map = parse_formula_v2(formula).unwrap();
+        let map = parse_formula_v2(formula).unwrap().atoms;
         let mut exp = HashMap::new();
-        exp.insert(Element::from_symbol("C").unwrap(), 7);
-        exp.insert(Element::from_symbol("H").unwrap(), 6);
-        exp.insert(Element::from_symbol("O").unwrap(), 2);
+        exp.insert(Element::from_symbol("C").unwrap(), Rational::from(7));
+        exp.insert(Element::from_symbol("H").unwrap(), Rational::from(6));
+        exp.insert(Element::from_symbol("O").unwrap(), Rational::from(2));
         assert_eq!(map, exp);
     }
 
     #[test]
     fn test_brackets() {
         let formula = "H2[SO4]2";
-        let map = parse_formula_v2(formula).unwrap();
+        let map = parse_formula_v2(formula).unwrap().atoms;
         let mut exp = HashMap::new();
-        exp.insert(Element::from_symbol("H").unwrap(), 2);
-        exp.insert(Element::from_symbol("S").unwrap(), 2);
-        exp.insert(Element::from_symbol("O").unwrap(), 8);
+        exp.insert(Element::from_symbol("H").unwrap(), Rational::from(2));
+        exp.insert(Element::from_symbol("S").unwrap(), Rational::from(2));
+        exp.insert(Element::from_symbol("O").unwrap(), Rational::from(8));
         assert_eq!(map, exp);
     }
 
+    #[test]
+    fn test_charges() {
+        let charges = vec![
+            ("MnO4-", -1),
+            ("NH4+", 1),
+            ("Fe+3", 3),
+            ("Fe^3+", 3),
+            ("SO4^2-", -2),
+            ("SO4-2", -2),
+            ("SO4--", -2),
+            ("H2O", 0),
+            ("e-", -1),
+        ];
+        for (formula, expected) in charges {
+            let parsed = parse_formula_v2(formula).unwrap();
+            assert_eq!(parsed.charge, expected, "{}", formula);
+        }
+    }
+
+    #[test]
+    fn test_ambiguous_charges() {
+        for formula in ["Fe3+", "O2-", "H2+"] {
+            let error = parse_formula_v2(formula).unwrap_err();
+            assert_eq!(error.offset, formula.len() - 1, "{}", formula);
+        }
+        let error = parse_formula_v2("Fe3+").unwrap_err();
+        assert_eq!(error.suggestions, vec!["Fe^3+", "Fe3^+"]);
+        assert_eq!(parse_formula_v2("Fe3^+").unwrap().charge, 1);
+        assert_eq!(parse_formula_v2("NH4+").unwrap().charge, 1);
+        assert_eq!(parse_formula_v2("Cl-").unwrap().charge, -1);
+    }
+
+    #[test]
+    fn test_ion_atoms() {
+        let formula = "SO4^2-";
+        let map = parse_formula_v2(formula).unwrap().atoms;
+        let mut exp = HashMap::new();
+        exp.insert(Element::from_symbol("S").unwrap(), Rational::from(1));
+        exp.insert(Element::from_symbol("O").unwrap(), Rational::from(4));
+        assert_eq!(map, exp);
+        assert!(parse_formula_v2("e-").unwrap().atoms.is_empty());
+    }
+
     #[test]
     fn test_curly_brace() {
         let formula = "H2{SO4}2";
-        let map = parse_formula_v2(formula).unwrap();
+        let map = parse_formula_v2(formula).unwrap().atoms;
         let mut exp = HashMap::new();
-        exp.insert(Element::from_symbol("H").unwrap(), 2);
-        exp.insert(Element::from_symbol("S").unwrap(), 2);
-        exp.insert(Element::from_symbol("O").unwrap(), 8);
+        exp.insert(Element::from_symbol("H").unwrap(), Rational::from(2));
+        exp.insert(Element::from_symbol("S").unwrap(), Rational::from(2));
+        exp.insert(Element::from_symbol("O").unwrap(), Rational::from(8));
         assert_eq!(map, exp);
     }
+
+    #[test]
+    fn ethane() {
+        let expected: HashMap<&str, usize> =
+            [("C", 2), ("H", 6)].iter().cloned().collect();
+        assert_eq!(counts("C2H6"), parse_elements(expected));
+    }
+
+    #[test]
+    fn nickel_tert_butoxide() {
+        let expected: HashMap<&str, usize> =
+            [("Ni", 1), ("O", 2), ("C", 8), ("H", 18)]
+                .iter()
+                .cloned()
+                .collect();
+        assert_eq!(counts("Ni[OC(CH3)3]2"), parse_elements(expected));
+    }
+
+    #[test]
+    fn test_nested_brackets() {
+        let expected: HashMap<&str, usize> =
+            [("K", 4), ("Fe", 1), ("C", 6), ("N", 6)]
+                .iter()
+                .cloned()
+                .collect();
+        assert_eq!(counts("K4[Fe(CN)6]"), parse_elements(expected));
+        let expected: HashMap<&str, usize> =
+            [("Co", 1), ("N", 6), ("H", 18), ("Cl", 3)]
+                .iter()
+                .cloned()
+                .collect();
+        assert_eq!(counts("[Co(NH3)6]Cl3"), parse_elements(expected));
+        let expected: HashMap<&str, usize> =
+            [("C", 8), ("H", 8)].iter().cloned().collect();
+        assert_eq!(counts("{[(CH)2]2}2"), parse_elements(expected));
+    }
+
+    #[test]
+    fn missing_open_bracket() {
+        let error = parse_formula_v2("(C2H3)3)").unwrap_err();
+        assert_eq!(error.offset, 7);
+        assert_eq!(error.token, ")");
+    }
+
+    #[test]
+    fn missing_closing_bracket() {
+        let error = parse_formula_v2("((C2H3)3").unwrap_err();
+        assert_eq!(error.offset, 8);
+        assert_eq!(error.expected, vec![")"]);
+    }
+
+    #[test]
+    fn mismatched_bracket_types() {
+        let error = parse_formula_v2("{C2H6)12").unwrap_err();
+        assert_eq!(error.offset, 5);
+        assert_eq!(error.token, ")");
+        assert_eq!(error.expected, vec!["}"]);
+        let error = parse_formula_v2("H2(SO4]2").unwrap_err();
+        assert_eq!(error.offset, 6);
+        assert_eq!(error.expected, vec![")"]);
+    }
+
+    #[test]
+    fn invalid_symbol() {
+        assert!(parse_formula_v2("CkCoNv30").is_err());
+    }
+
+    #[test]
+    fn invalid_string() {
+        assert!(parse_formula_v2("30k").is_err());
+    }
+
+    #[test]
+    fn test_adducts() {
+        for formula in ["CuSO4·5H2O", "CuSO4*5H2O"] {
+            let parsed = parse_formula_v2(formula).unwrap();
+            assert_eq!(parsed.base, "CuSO4");
+            assert_eq!(parsed.adducts.len(), 1);
+            assert_eq!(parsed.adducts[0].count, 5);
+            assert_eq!(parsed.adducts[0].formula, "H2O");
+        }
+        let expected: HashMap<&str, usize> =
+            [("Ca", 1), ("Cl", 2), ("N", 2), ("H", 6)]
+                .iter()
+                .cloned()
+                .collect();
+        assert_eq!(counts("CaCl2·2NH3"), parse_elements(expected));
+        let expected: HashMap<&str, usize> =
+            [("Na", 2), ("C", 1), ("O", 5), ("H", 2)]
+                .iter()
+                .cloned()
+                .collect();
+        assert_eq!(counts("Na2CO3·H2O2"), parse_elements(expected));
+    }
+
+    #[test]
+    fn test_adduct_chain() {
+        let parsed = parse_formula_v2("AlCl3·2NH3·3H2O").unwrap();
+        let adducts: Vec<(Rational, &str)> = parsed
+            .adducts
+            .iter()
+            .map(|a| (a.count.clone(), a.formula.as_str()))
+            .collect();
+        assert_eq!(
+            adducts,
+            vec![(Rational::from(2), "NH3"), (Rational::from(3), "H2O")]
+        );
+        let expected: HashMap<&str, usize> =
+            [("Al", 1), ("Cl", 3), ("N", 2), ("H", 12), ("O", 3)]
+                .iter()
+                .cloned()
+                .collect();
+        assert_eq!(counts("AlCl3·2NH3·3H2O"), parse_elements(expected));
+    }
+
+    #[test]
+    fn test_isotopes() {
+        let carbon13 = Element::from_symbol("C").unwrap().with_mass_number(13);
+        let deuterium = Element::from_symbol("D").unwrap();
+        for formula in ["[13C]H3I", "^13CH3I", "¹³CH₃I"] {
+            let atoms = parse_formula_v2(formula).unwrap().atoms;
+            assert_eq!(
+                atoms.get(&carbon13.unwrap()),
+                Some(&Rational::from(1)),
+                "{}",
+                formula
+            );
+            assert!(!atoms.contains_key(&Element::from_symbol("C").unwrap()));
+        }
+        let atoms = parse_formula_v2("CD3OD").unwrap().atoms;
+        assert_eq!(atoms.get(&deuterium), Some(&Rational::from(4)));
+        let atoms = parse_formula_v2("[18O]H2").unwrap().atoms;
+        let oxygen18 = Element::from_symbol("O").unwrap().with_mass_number(18);
+        assert_eq!(atoms.get(&oxygen18.unwrap()), Some(&Rational::from(1)));
+        assert_eq!(parse_formula_v2("T2").unwrap().atoms.len(), 1);
+        assert!(parse_formula_v2("[99C]H4").is_err());
+    }
+
+    #[test]
+    fn test_decimal_counts() {
+        let oxygen = Element::from_symbol("O").unwrap();
+        let counts = [
+            ("Fe0.95O", "Fe", (19, 20)),
+            ("YBa2Cu3O6.93", "O", (693, 100)),
+            ("LiNi0.8Co0.1Mn0.1O2", "Ni", (4, 5)),
+            ("Li1.05Mn2O4", "Li", (21, 20)),
+            ("La1.85Sr0.15CuO4", "La", (37, 20)),
+            ("Li1.5Mn2O4", "Li", (3, 2)),
+            ("Na1.5Fe(CN)6", "Na", (3, 2)),
+            ("Li2.5Mn2O4", "Li", (5, 2)),
+            ("Na0.67MnO2", "Na", (67, 100)),
+        ];
+        for (formula, symbol, expected) in counts {
+            let atoms = parse_formula_v2(formula).unwrap().atoms;
+            let element = Element::from_symbol(symbol).unwrap();
+            assert_eq!(
+                atoms[&element],
+                Rational::from(expected),
+                "{}",
+                formula
+            );
+        }
+        // a . between digits is a decimal point, never an adduct separator
+        let parsed = parse_formula_v2("CuSO4.5H2O").unwrap();
+        assert!(parsed.adducts.is_empty());
+        assert_eq!(parsed.atoms[&oxygen], Rational::from((11, 2)));
+        let parsed = parse_formula_v2("BF3.NH3").unwrap();
+        assert_eq!(parsed.adducts.len(), 1);
+        let parsed = parse_formula_v2("CaSO4·0.5H2O").unwrap();
+        assert_eq!(parsed.adducts[0].count, Rational::from((1, 2)));
+    }
 }
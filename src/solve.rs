@@ -1,117 +1,387 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
 use itertools::Itertools;
-use nalgebra::DMatrix;
-use num::integer::lcm;
-use rug::Rational;
+use rug::{Integer, Rational};
 
 use crate::model::Compound;
 use crate::model::Element;
 
-pub fn build_matrix(
-    all_elements: &Vec<&Element>,
-    all_compounds: Vec<&Compound>,
-    ncols: usize,
-) -> DMatrix<f64> {
-    let mut matrix: Vec<f64> = Vec::new();
-    debug!("Building matrix");
-    for element in all_elements {
-        debug!("Getting coefficients for {:?}", element);
-        for compound in all_compounds.clone() {
-            let coefficient =
-                compound.atoms.get(element).cloned().unwrap_or(0_usize);
-            trace!(
-                "Pushing {:?}*{:?} from {:?}",
-                coefficient,
-                element,
-                compound
-            );
-            matrix.push(coefficient as f64);
+pub type Matrix = Vec<Vec<Rational>>;
+
+/// A row of the stoichiometric matrix: an element, or the net charge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RowLabel {
+    Element(Element),
+    Charge,
+}
+
+impl Display for RowLabel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RowLabel::Element(element) => write!(f, "{}", element),
+            RowLabel::Charge => write!(f, "charge"),
         }
     }
-    debug!("Constructing matrix");
-    DMatrix::from_row_slice(all_elements.len(), ncols, matrix.as_slice())
 }
 
-pub fn solve_system(
-    mx: DMatrix<f64>,
-    ncols: usize,
-) -> Result<Vec<f64>, String> {
-    let (a, b) = mx.columns_range_pair(0..ncols, ncols..);
-    let solution = if !a.is_square() {
-        debug!("Solving non-square matrix by SVD");
-        let x = a.svd(true, true);
-        debug!("Solving equation system");
-        x.solve(&b, 0.0)?
-    } else {
-        debug!("Solving square matrix by LU");
-        let x = a.lu();
-        debug!("Solving equation system");
-        x.solve(&b)
-            .unwrap_or_else(|| panic!("Failed to solve matrix! {:?}", x))
-    };
-    let coefficients: Vec<f64> = solution
-        .column(0)
-        .into_iter()
-        .map(|c| c.clone())
-        .collect_vec();
-    Ok(coefficients)
+/// The element-by-species matrix of a reaction, with one row per element
+/// (ordered by atomic number) and one column per species, in the order
+/// given. Product columns are negated, so a balanced reaction is a vector
+/// in the nullspace of the matrix. If any species is charged, a last row
+/// conserves the net charge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoichiometricMatrix {
+    pub rows: Vec<RowLabel>,
+    /// Species formulas, reactants first
+    pub columns: Vec<String>,
+    pub reactant_count: usize,
+    pub entries: Matrix,
 }
 
-pub fn normalize_coefficients(
-    coefficients: &Vec<f64>,
-) -> Result<Vec<usize>, String> {
-    let rational_coefficients: Vec<Rational> = coefficients
-        .into_iter()
-        .map(|c| {
-            trace!("Constructing rational from {:?}", &c);
-            Rational::from_f64(c.clone()).ok_or_else(|| {
-                format!("Could not construct rational from: {:?}", &c)
+impl StoichiometricMatrix {
+    pub fn new(
+        reactants: Vec<&Compound>,
+        products: Vec<&Compound>,
+    ) -> StoichiometricMatrix {
+        debug!("Building matrix");
+        let reactant_count = reactants.len();
+        let signed_compounds: Vec<(&Compound, i32)> = reactants
+            .into_iter()
+            .map(|c| (c, 1))
+            .chain(products.into_iter().map(|c| (c, -1)))
+            .collect();
+        let mut rows: Vec<RowLabel> = signed_compounds
+            .iter()
+            .flat_map(|(c, _)| c.all_elements())
+            .map(|e| RowLabel::Element(*e))
+            .collect();
+        if signed_compounds.iter().any(|(c, _)| c.is_charged()) {
+            rows.push(RowLabel::Charge);
+        }
+        rows.sort();
+        rows.dedup();
+        let entries: Matrix = rows
+            .iter()
+            .map(|row| {
+                signed_compounds
+                    .iter()
+                    .map(|(compound, sign)| {
+                        let coefficient = match row {
+                            RowLabel::Element(element) => compound
+                                .atoms
+                                .get(element)
+                                .cloned()
+                                .unwrap_or_default(),
+                            RowLabel::Charge => Rational::from(compound.charge),
+                        };
+                        trace!(
+                            "Pushing {:?}*{} from {:?}",
+                            coefficient,
+                            row,
+                            compound
+                        );
+                        coefficient * *sign
+                    })
+                    .collect()
             })
+            .collect();
+        StoichiometricMatrix {
+            rows,
+            columns: signed_compounds
+                .iter()
+                .map(|(c, _)| c.formula.clone())
+                .collect(),
+            reactant_count,
+            entries,
+        }
+    }
+
+    pub fn nrows(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn ncols(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn nullspace(&self) -> Matrix {
+        nullspace(&self.entries, self.ncols())
+    }
+
+    pub fn rank(&self) -> usize {
+        rank(&self.entries, self.ncols())
+    }
+
+    /// The same matrix without the given row
+    pub fn without_row(&self, label: &RowLabel) -> StoichiometricMatrix {
+        let (rows, entries) = self
+            .rows
+            .iter()
+            .zip(self.entries.iter())
+            .filter(|(row, _)| *row != label)
+            .map(|(row, values)| (*row, values.clone()))
+            .unzip();
+        StoichiometricMatrix {
+            rows,
+            entries,
+            ..self.clone()
+        }
+    }
+
+    /// The same matrix without the species in the given column
+    pub fn without_column(&self, col: usize) -> StoichiometricMatrix {
+        let keep = |values: &[Rational]| -> Vec<Rational> {
+            values
+                .iter()
+                .enumerate()
+                .filter(|(c, _)| *c != col)
+                .map(|(_, v)| v.clone())
+                .collect()
+        };
+        let mut columns = self.columns.clone();
+        columns.remove(col);
+        StoichiometricMatrix {
+            rows: self.rows.clone(),
+            columns,
+            reactant_count: match col < self.reactant_count {
+                true => self.reactant_count - 1,
+                false => self.reactant_count,
+            },
+            entries: self.entries.iter().map(|row| keep(row)).collect(),
+        }
+    }
+
+    /// Exports the matrix as comma separated values, with a header of
+    /// species and one labelled line per row
+    pub fn to_csv(&self) -> String {
+        let header = std::iter::once(String::new())
+            .chain(self.columns.iter().cloned())
+            .join(",");
+        let lines = self.rows.iter().zip(self.entries.iter()).map(|(r, v)| {
+            std::iter::once(r.to_string())
+                .chain(v.iter().map(|x| x.to_string()))
+                .join(",")
+        });
+        std::iter::once(header).chain(lines).join("\n")
+    }
+}
+
+impl Display for StoichiometricMatrix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let cells: Vec<Vec<String>> = std::iter::once(
+            std::iter::once(String::new())
+                .chain(self.columns.iter().cloned())
+                .collect(),
+        )
+        .chain(self.rows.iter().zip(self.entries.iter()).map(|(r, v)| {
+            std::iter::once(r.to_string())
+                .chain(v.iter().map(|x| x.to_string()))
+                .collect()
+        }))
+        .collect();
+        let widths: Vec<usize> = (0..=self.ncols())
+            .map(|c| cells.iter().map(|line| line[c].len()).max().unwrap_or(0))
+            .collect();
+        let mut lines = cells.iter().map(|line| {
+            line.iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+                .join("  ")
+        });
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Computes a basis of the nullspace of `mx` exactly, by reducing it to
+/// reduced row echelon form. Each basis vector has a 1 in one free column.
+pub fn nullspace(mx: &[Vec<Rational>], ncols: usize) -> Matrix {
+    let mut rows: Matrix = mx.to_vec();
+    let mut pivots: Vec<usize> = Vec::new();
+    for col in 0..ncols {
+        let row = pivots.len();
+        if row == rows.len() {
+            break;
+        }
+        let pivot_row = match (row..rows.len())
+            .find(|&r| rows[r][col].cmp0() != Ordering::Equal)
+        {
+            Some(r) => r,
+            None => continue,
+        };
+        rows.swap(row, pivot_row);
+        let pivot = rows[row][col].clone();
+        for value in rows[row].iter_mut() {
+            *value /= &pivot;
+        }
+        let reduced = rows[row].clone();
+        for (r, other) in rows.iter_mut().enumerate() {
+            if r == row || other[col].cmp0() == Ordering::Equal {
+                continue;
+            }
+            let factor = other[col].clone();
+            for (value, pivot_value) in other.iter_mut().zip(reduced.iter()) {
+                *value -= Rational::from(&factor * pivot_value);
+            }
+        }
+        pivots.push(col);
+    }
+    trace!("Reduced matrix: {:?}, pivots: {:?}", rows, pivots);
+    (0..ncols)
+        .filter(|col| !pivots.contains(col))
+        .map(|free| {
+            let mut vector = vec![Rational::new(); ncols];
+            vector[free] = Rational::from(1);
+            for (row, &col) in pivots.iter().enumerate() {
+                vector[col] = Rational::from(-&rows[row][free]);
+            }
+            vector
         })
-        .collect::<Result<Vec<Rational>, String>>()?;
-    trace!("Got rational coefficients: {:?}", &rational_coefficients);
-    trace!("Limiting denominators");
-    let rational_limited: Vec<Rational> = rational_coefficients
+        .collect()
+}
+
+pub fn rank(mx: &[Vec<Rational>], ncols: usize) -> usize {
+    ncols - nullspace(mx, ncols).len()
+}
+
+pub fn solve_system(mx: Matrix, ncols: usize) -> Result<Vec<Rational>, String> {
+    debug!("Solving equation system");
+    let mut basis = nullspace(&mx, ncols);
+    match basis.len() {
+        0 => Err("Equation has no non-zero solution!".to_string()),
+        1 => Ok(basis.remove(0)),
+        n => Err(format!(
+            "Equation has {} independent solutions, cannot choose one!",
+            n
+        )),
+    }
+}
+
+/// Finds the support-minimal solutions with all coefficients positive, i.e.
+/// the smallest sub-reactions that balance with every species on its given
+/// side. Any all-positive solution is a combination of these.
+pub fn minimal_solutions(
+    mx: &[Vec<Rational>],
+    ncols: usize,
+) -> Result<Matrix, String> {
+    let supports = minimal_supports(mx, ncols, |signs| {
+        signs[0] != Ordering::Equal && signs.iter().all(|s| *s == signs[0])
+    })?;
+    Ok(supports
         .into_iter()
-        .map(move |mut r| {
-            r.abs_mut();
-            limit_denominator(r, 100)
-        })
-        .collect::<Result<Vec<Rational>, String>>()?;
-    trace!("Limited rational coefficients: {:?}", rational_limited);
-    let denominators: Vec<usize> = rational_limited
-        .iter()
-        .map(|c| {
-            c.denom().to_usize().ok_or_else(|| {
-                format!(
-                    "Could not convert denominator {:?} to u64!",
-                    &c.denom()
-                )
-            })
+        .map(|(subset, vector)| {
+            let flip = vector[0].cmp0() == Ordering::Less;
+            let mut solution = vec![Rational::new(); ncols];
+            for (&col, value) in subset.iter().zip(vector) {
+                solution[col] = if flip { -value } else { value };
+            }
+            solution
         })
-        .collect::<Result<Vec<usize>, String>>()?;
-    trace!("Got denominators: {:?}", &denominators);
-    let scale: usize =
-        denominators
+        .collect())
+}
+
+/// Finds the smallest sets of columns that are linearly dependent, i.e.
+/// groups of species that balance among themselves on some sides.
+pub fn dependent_sets(
+    mx: &[Vec<Rational>],
+    ncols: usize,
+) -> Result<Vec<Vec<usize>>, String> {
+    let supports = minimal_supports(mx, ncols, |signs| {
+        signs.iter().all(|s| *s != Ordering::Equal)
+    })?;
+    Ok(supports.into_iter().map(|(subset, _)| subset).collect())
+}
+
+/// Columns with the nullspace vector of their sub-matrix
+type Support = (Vec<usize>, Vec<Rational>);
+
+/// The most species whose column subsets are searched for minimal
+/// supports, as the number of subsets grows exponentially
+pub const MAX_SUPPORT_COLUMNS: usize = 16;
+
+/// Enumerates column subsets, smallest first, whose sub-matrix has a
+/// one-dimensional nullspace with signs accepted by `accept`, skipping
+/// supersets of subsets already found. A minimal dependent set has at most
+/// one column more than the rank, so larger subsets are not tried.
+fn minimal_supports(
+    mx: &[Vec<Rational>],
+    ncols: usize,
+    accept: impl Fn(&[Ordering]) -> bool,
+) -> Result<Vec<Support>, String> {
+    if ncols > MAX_SUPPORT_COLUMNS {
+        return Err(format!(
+            "Too many species to search ({}, at most {})",
+            ncols, MAX_SUPPORT_COLUMNS
+        ));
+    }
+    let largest = (rank(mx, ncols) + 1).min(ncols);
+    let mut found: Vec<Support> = Vec::new();
+    for subset in (1..=largest).flat_map(|k| (0..ncols).combinations(k)) {
+        if found
             .iter()
-            .cloned()
-            .combinations(2)
-            .fold(1, |acc, cur| {
-                let cur_lcm = lcm(cur[0], cur[1]);
-                acc.max(cur_lcm)
-            });
+            .any(|(support, _)| support.iter().all(|c| subset.contains(c)))
+        {
+            continue;
+        }
+        let sub_matrix: Matrix = mx
+            .iter()
+            .map(|row| subset.iter().map(|&c| row[c].clone()).collect())
+            .collect();
+        let mut basis = nullspace(&sub_matrix, subset.len());
+        if basis.len() != 1 {
+            continue;
+        }
+        let vector = basis.remove(0);
+        let signs: Vec<Ordering> = vector.iter().map(|v| v.cmp0()).collect();
+        if !accept(&signs) {
+            continue;
+        }
+        trace!("Found minimal support on columns {:?}", subset);
+        found.push((subset, vector));
+    }
+    Ok(found)
+}
+
+/// Scales a solution vector to the smallest all-positive integers.
+pub fn normalize_coefficients(
+    coefficients: &[Rational],
+) -> Result<Vec<Integer>, String> {
+    let integers = scale_to_integers(coefficients);
+    trace!("Got scaled coefficients: {:?}", integers);
+    let signs: Vec<Ordering> = integers.iter().map(|i| i.cmp0()).collect();
+    let integers: Vec<Integer> = if signs.contains(&Ordering::Greater) {
+        integers
+    } else {
+        integers.into_iter().map(|i| -i).collect()
+    };
+    if integers.iter().any(|i| i.cmp0() == Ordering::Less) {
+        return Err(format!(
+            "Equation cannot be balanced with species on the given sides! Got solution: {:?}",
+            integers
+        ));
+    }
+    Ok(integers)
+}
+
+/// Multiplies by the least common denominator, then divides by the
+/// greatest common divisor, keeping signs.
+pub fn scale_to_integers(coefficients: &[Rational]) -> Vec<Integer> {
+    let scale = coefficients
+        .iter()
+        .fold(Integer::from(1), |acc, c| acc.lcm(c.denom()));
     debug!("Scaling coefficients by: {}", scale);
-    let mut scaled_coefficients: Vec<usize> = rational_limited
+    let scaled: Vec<Integer> = coefficients
         .iter()
-        .map(|f| f * Rational::from((scale, 1)))
-        .map(|f| {
-            f.numer().to_usize().ok_or_else(|| {
-                format!("Could not convert scaled {:?} to u64", &f.numer())
-            })
-        })
-        .collect::<Result<Vec<usize>, String>>()?;
-    scaled_coefficients.push(scale);
-    trace!("Got scaled coefficients: {:?}", scaled_coefficients);
-    Ok(scaled_coefficients)
+        .map(|c| c.numer() * Integer::from(&scale / c.denom()))
+        .collect();
+    let divisor = scaled.iter().fold(Integer::new(), |acc, i| acc.gcd(i));
+    if divisor.cmp0() == Ordering::Equal {
+        return scaled;
+    }
+    scaled
+        .into_iter()
+        .map(|i| Integer::from(&i / &divisor))
+        .collect()
 }
 
 pub fn limit_denominator(
@@ -173,6 +443,7 @@ pub fn limit_denominator(
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod tests {
+    use super::*;
     use crate::model::Reaction;
     use crate::model::*;
 
@@ -302,6 +573,134 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_stoichiometric_matrix() {
+        let rxn = new_reaction!(CH4 + O2 = CO2 + H2O).unwrap();
+        let mx = rxn.matrix();
+        let labels: Vec<String> =
+            mx.rows.iter().map(|r| r.to_string()).collect();
+        assert_eq!(labels, vec!["H", "C", "O"]);
+        assert_eq!(mx.columns, vec!["CH4", "O2", "CO2", "H2O"]);
+        assert_eq!(mx.reactant_count, 2);
+        assert_eq!(mx.rank(), 3);
+        assert_eq!(
+            mx.to_csv(),
+            ",CH4,O2,CO2,H2O\nH,4,0,0,-2\nC,1,0,-1,0\nO,0,2,-2,-1"
+        );
+        assert_eq!(rxn.matrix(), mx);
+    }
+
+    #[test]
+    fn test_stoichiometric_matrix_charge() {
+        let reaction = Reaction::skeleton(
+            _formulas_to_compounds(vec!["MnO4-", "Fe+2", "H+"]),
+            _formulas_to_compounds(vec!["Mn+2", "Fe+3", "H2O"]),
+        );
+        let mx = reaction.matrix();
+        let labels: Vec<String> =
+            mx.rows.iter().map(|r| r.to_string()).collect();
+        assert_eq!(labels, vec!["H", "O", "Mn", "Fe", "charge"]);
+        assert_eq!(
+            mx.entries[4],
+            vec![-1, 2, 1, -2, -3, 0]
+                .into_iter()
+                .map(Rational::from)
+                .collect::<Vec<Rational>>()
+        );
+        let without = mx.without_row(&RowLabel::Charge).without_column(0);
+        assert_eq!(without.nrows(), 4);
+        assert_eq!(without.columns[0], "Fe+2");
+        assert_eq!(without.reactant_count, 2);
+    }
+
+    #[test]
+    fn test_failure_zero_species() {
+        let rxn = new_reaction!(Cu + HNO3 = CuN2O6 + NO2 + H2O2).unwrap();
+        let failure = rxn.balance().unwrap_err();
+        assert_eq!(failure.kind, FailureKind::ZeroCoefficients);
+        assert_eq!(failure.zero_species, vec!["Cu", "CuN2O6"]);
+    }
+
+    #[test]
+    fn test_failure_missing_element() {
+        let reaction = Reaction::skeleton(
+            _formulas_to_compounds(vec!["H2", "O2"]),
+            _formulas_to_compounds(vec!["H2O", "N2"]),
+        );
+        let failure = reaction.balance().unwrap_err();
+        assert_eq!(failure.kind, FailureKind::ZeroCoefficients);
+        let nitrogen = Element::from_symbol("N").unwrap();
+        assert_eq!(
+            failure.inconsistent_rows,
+            vec![RowLabel::Element(nitrogen)]
+        );
+        assert_eq!(failure.zero_species, vec!["N2"]);
+        assert!(failure
+            .suggestions
+            .contains(&"add a reactant containing N".to_string()));
+    }
+
+    #[test]
+    fn test_failure_charge() {
+        let reaction = Reaction::skeleton(
+            _formulas_to_compounds(vec!["Fe+3"]),
+            _formulas_to_compounds(vec!["Fe+2"]),
+        );
+        let failure = reaction.balance().unwrap_err();
+        assert_eq!(failure.kind, FailureKind::NoSolution);
+        let iron = Element::from_symbol("Fe").unwrap();
+        assert_eq!(
+            failure.inconsistent_rows,
+            vec![RowLabel::Element(iron), RowLabel::Charge]
+        );
+    }
+
+    #[test]
+    fn test_failure_ambiguous() {
+        let rxn = new_reaction!(H2O2 = O2 + O3 + H2O).unwrap();
+        let failure = rxn.balance().unwrap_err();
+        assert_eq!(failure.kind, FailureKind::Ambiguous(2));
+        assert_eq!(failure.redundant_species, vec!["O3"]);
+        assert!(failure
+            .dependent_species
+            .contains(&vec!["O2".to_string(), "O3".to_string()]));
+        assert!(failure
+            .suggestions
+            .contains(&"remove O3 to get a single balancing".to_string()));
+    }
+
+    #[test]
+    fn test_failure_too_many_species() {
+        let alkanes: Vec<String> =
+            (1..=18).map(|n| format!("C{}H{}", n, 2 * n + 2)).collect();
+        let reaction = Reaction::new(
+            _formulas_to_compounds(vec!["C", "H2"]),
+            _formulas_to_compounds(
+                alkanes.iter().map(String::as_str).collect(),
+            ),
+        )
+        .unwrap();
+        let failure = reaction.balance().unwrap_err();
+        assert_eq!(failure.kind, FailureKind::Ambiguous(18));
+        assert!(failure.dependent_species.is_empty());
+        assert!(failure
+            .suggestions
+            .iter()
+            .any(|s| s.starts_with("too many species")));
+        assert!(reaction.balance_all().is_err());
+    }
+
+    #[test]
+    fn test_failure_wrong_sides() {
+        let reaction = Reaction::skeleton(
+            _formulas_to_compounds(vec!["H2"]),
+            _formulas_to_compounds(vec!["H2O", "O2"]),
+        );
+        let failure = reaction.balance().unwrap_err();
+        assert_eq!(failure.kind, FailureKind::WrongSides);
+        assert_eq!(failure.misplaced_species, vec!["O2"]);
+    }
+
     #[test]
     fn test_batch() {
         expect_coefficients!(CO2 + H2O = C6H12O6 + O2 => 6 + 6 + 1 + 6);
@@ -315,4 +714,211 @@ mod tests {
         expect_coefficients!(Al2S3O12 + CaO2H2 = AlO3H3 + CaSO4 => 1 + 3 + 2 + 3);
         expect_coefficients!(H2SO4 + HI = H2S + I2 + H2O => 1 + 8 + 1 + 4 + 4);
     }
+
+    #[test]
+    fn test_large_coefficients() {
+        // denominators above 100 are out of reach of an f64 approximation
+        let reaction = Reaction::new(
+            _formulas_to_compounds(vec!["K4Fe(CN)6", "KMnO4", "H2SO4"]),
+            _formulas_to_compounds(vec![
+                "KHSO4",
+                "Fe2(SO4)3",
+                "MnSO4",
+                "HNO3",
+                "CO2",
+                "H2O",
+            ]),
+        )
+        .unwrap();
+        let solution = reaction.balance().unwrap();
+        assert_eq!(
+            solution.all_coefficients(),
+            vec![10, 122, 299, 162, 5, 122, 60, 60, 188]
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_reaction() {
+        let rxn = new_reaction!(H2O2 = H2O + O2 + H2).unwrap();
+        assert!(rxn.balance().is_err());
+        let balancings = rxn.balance_all().unwrap();
+        assert_eq!(balancings.dimension(), 2);
+        let expected = vec![
+            BalancedReaction::new(
+                vec![parse_balanced_reagent!((H2O2, 2))],
+                vec![
+                    parse_balanced_reagent!((H2O, 2)),
+                    parse_balanced_reagent!(O2),
+                ],
+            )
+            .unwrap(),
+            BalancedReaction::new(
+                vec![parse_balanced_reagent!(H2O2)],
+                vec![parse_balanced_reagent!(O2), parse_balanced_reagent!(H2)],
+            )
+            .unwrap(),
+        ];
+        assert_eq!(balancings.minimal, expected);
+        assert_eq!(balancings.basis, expected);
+    }
+
+    #[test]
+    fn test_balance_all_unique() {
+        let rxn = new_reaction!(H2 + O2 = H2O).unwrap();
+        let balancings = rxn.balance_all().unwrap();
+        assert!(!balancings.is_ambiguous());
+        assert_eq!(balancings.basis, vec![rxn.balance().unwrap()]);
+    }
+
+    #[test]
+    fn test_ionic() {
+        // balancing the atoms alone leaves two independent solutions
+        let reaction = Reaction::new(
+            _formulas_to_compounds(vec!["MnO4-", "Fe+2", "H+"]),
+            _formulas_to_compounds(vec!["Mn+2", "Fe+3", "H2O"]),
+        )
+        .unwrap();
+        let solution = reaction.balance().unwrap();
+        assert_eq!(solution.all_coefficients(), vec![1, 5, 8, 1, 5, 4]);
+    }
+
+    #[test]
+    fn test_charge_not_balanced() {
+        let reactants = vec![Reactant::from_formula("Fe+3", 1).unwrap()];
+        let products = vec![Reactant::from_formula("Fe+2", 1).unwrap()];
+        assert!(BalancedReaction::new(reactants, products).is_err());
+    }
+
+    #[test]
+    fn test_balance_with() {
+        let rxn = new_reaction!(C8H18 + O2 = CO2 + H2O).unwrap();
+        let fuel = Compound::from_formula("C8H18").unwrap();
+        let solution = rxn.balance_with(&fuel, 1).unwrap();
+        assert_eq!(
+            solution.all_coefficients(),
+            vec![
+                Rational::from(1),
+                Rational::from((25, 2)),
+                Rational::from(8),
+                Rational::from(9)
+            ]
+        );
+        assert_eq!(
+            solution.display_string(false),
+            "C8H18 + 25/2*O2 = 8*CO2 + 9*H2O"
+        );
+        assert_eq!(
+            solution.decimal_display_string(false),
+            "C8H18 + 12.5*O2 = 8*CO2 + 9*H2O"
+        );
+    }
+
+    #[test]
+    fn test_isotope_labels_conserved() {
+        let labelled = Reaction::new(
+            _formulas_to_compounds(vec!["[13C]H3I", "NaOH"]),
+            _formulas_to_compounds(vec!["[13C]H3OH", "NaI"]),
+        )
+        .unwrap();
+        assert_eq!(
+            labelled.balance().unwrap().display_string(false),
+            "[13C]H3I + NaOH = [13C]H3OH + NaI"
+        );
+        let unlabelled = Reaction::skeleton(
+            _formulas_to_compounds(vec!["[13C]H3I", "NaOH"]),
+            _formulas_to_compounds(vec!["CH3OH", "NaI"]),
+        );
+        assert!(unlabelled.balance().is_err());
+        expect_balanced!(
+            D2 + O2 = D2O =>
+                (D2, 2) + (O2, 1) = (D2O, 2)
+        );
+    }
+
+    #[test]
+    fn test_fractional_formulas() {
+        let reaction = Reaction::new(
+            _formulas_to_compounds(vec!["Fe", "O2"]),
+            _formulas_to_compounds(vec!["Fe0.95O"]),
+        )
+        .unwrap();
+        assert_eq!(
+            reaction.balance().unwrap().display_string(false),
+            "19*Fe + 10*O2 = 20*Fe0.95O"
+        );
+    }
+
+    #[test]
+    fn test_balance_with_missing_species() {
+        let rxn = new_reaction!(H2 + O2 = H2O).unwrap();
+        let nitrogen = Compound::from_formula("N2").unwrap();
+        assert!(rxn.balance_with(&nitrogen, 1).is_err());
+    }
+
+    #[test]
+    fn test_balance_unordered() {
+        let reaction = Reaction::skeleton(
+            _formulas_to_compounds(vec!["H2"]),
+            _formulas_to_compounds(vec!["H2O", "O2"]),
+        );
+        assert!(reaction.balance().is_err());
+        let unordered = reaction.balance_unordered().unwrap();
+        assert_eq!(
+            unordered.balanced.display_string(false),
+            "2*H2 + O2 = 2*H2O"
+        );
+        let moved: Vec<&str> =
+            unordered.moved.iter().map(|c| c.formula.as_str()).collect();
+        assert_eq!(moved, vec!["O2"]);
+    }
+
+    #[test]
+    fn test_balance_unordered_agrees() {
+        let reaction = new_reaction!(H2 + O2 = H2O).unwrap();
+        let unordered = reaction.balance_unordered().unwrap();
+        assert!(unordered.agrees_with_given_sides());
+        assert_eq!(
+            unordered.balanced.display_string(false),
+            "2*H2 + O2 = 2*H2O"
+        );
+    }
+
+    #[test]
+    fn test_balance_species() {
+        let species = _formulas_to_compounds(vec!["O2", "H2O", "C3H8", "CO2"]);
+        let balanced = Reaction::balance_species(species).unwrap();
+        assert_eq!(
+            balanced.display_string(false),
+            "5*O2 + C3H8 = 4*H2O + 3*CO2"
+        );
+    }
+
+    #[test]
+    fn test_scale_to_integers() {
+        let coefficients = vec![
+            Rational::from((1, 2)),
+            Rational::from((-3, 4)),
+            Rational::from(2),
+        ];
+        let scaled = scale_to_integers(&coefficients);
+        assert_eq!(scaled, vec![2, -3, 8]);
+    }
+
+    #[test]
+    fn test_nullspace() {
+        // 2a = c, 2b = c
+        let mx: Matrix = vec![
+            vec![Rational::from(2), Rational::new(), Rational::from(-1)],
+            vec![Rational::new(), Rational::from(2), Rational::from(-1)],
+        ];
+        let basis = nullspace(&mx, 3);
+        assert_eq!(
+            basis,
+            vec![vec![
+                Rational::from((1, 2)),
+                Rational::from((1, 2)),
+                Rational::from(1)
+            ]]
+        );
+    }
 }
@@ -1,11 +1,13 @@
 use std::collections::HashMap;
 
+use rug::Rational;
+
 use crate::model::{Element, ElementCounts};
 
 #[allow(dead_code)]
 pub fn parse_elements(expected: HashMap<&str, usize>) -> ElementCounts {
     expected
         .iter()
-        .map(|p| (Element::from_symbol(p.0).unwrap(), p.1.clone()))
+        .map(|p| (Element::from_symbol(p.0).unwrap(), Rational::from(*p.1)))
         .collect()
 }